@@ -24,7 +24,7 @@ mod sidx;
 
 #[cfg(feature = "sidx")]
 #[cfg_attr(docsrs, doc(cfg(feature = "sidx")))]
-pub use sidx::{SidxBox, SidxRange};
+pub use sidx::{SidxBox, SidxLocation, SidxRange};
 
 #[cfg(feature = "text-vtt")]
 mod mdhd;
@@ -12,14 +12,35 @@ use crate::{Mp4Parser, ParsedBox, Result, bail, data};
 
 /// Segment range.
 pub struct SidxRange {
+    /// Subsegment duration in seconds.
+    pub duration: f64,
     pub end: u64,
+    /// The range points to another `SIDX` box (hierarchical index) instead of media.
+    pub is_sidx: bool,
+    pub sap_delta_time: u32,
+    pub sap_type: u8,
     pub start: u64,
+    /// Earliest presentation time of the subsegment in seconds.
+    pub start_time: f64,
+    pub starts_with_sap: bool,
+}
+
+/// Position of the `SIDX` box inside a file.
+pub enum SidxLocation {
+    /// The `SIDX` box occupies these bytes (inclusive).
+    Found { end: u64, start: u64 },
+    /// More data is needed, starting from this byte.
+    Continue(u64),
+    /// Media data starts before any `SIDX` box.
+    Missing,
 }
 
 /// Mp4 `SegmentBase@indexRange` parser.
 /// `sidx_offset` is the starting byte of sidx box.
 pub struct SidxBox {
+    pub earliest_presentation_time: u64,
     pub ranges: Vec<SidxRange>,
+    pub timescale: u32,
 }
 
 impl SidxBox {
@@ -37,6 +58,55 @@ impl SidxBox {
         Ok(sidx_box.take())
     }
 
+    /// Locate the `SIDX` box of an on-demand mp4 file by walking its top level boxes.
+    /// `data` holds the leading bytes of the file, read from `offset`.
+    pub fn locate(data: &[u8], offset: u64) -> Result<SidxLocation> {
+        let location = data!(SidxLocation::Continue(offset));
+        let location_c1 = location.clone();
+        let location_c2 = location.clone();
+        let location_c3 = location.clone();
+        let location_c4 = location.clone();
+
+        let skip = move |box_: ParsedBox| {
+            *location_c1.borrow_mut() =
+                SidxLocation::Continue(offset + box_.start + box_.size as u64);
+            Ok(())
+        };
+
+        Mp4Parser::new()
+            .base_box("ftyp", skip.clone())
+            .base_box("styp", skip.clone())
+            .base_box("moov", skip.clone())
+            .base_box("free", skip.clone())
+            .base_box("skip", skip.clone())
+            .base_box("uuid", skip.clone())
+            .base_box("emsg", skip.clone())
+            .base_box("prft", skip)
+            .base_box("sidx", move |box_| {
+                let start = offset + box_.start;
+                *location_c2.borrow_mut() = SidxLocation::Found {
+                    end: start + box_.size as u64 - 1,
+                    start,
+                };
+                box_.parser.stop();
+                Ok(())
+            })
+            .base_box("moof", move |box_| {
+                *location_c3.borrow_mut() = SidxLocation::Missing;
+                box_.parser.stop();
+                Ok(())
+            })
+            .base_box("mdat", move |box_| {
+                *location_c4.borrow_mut() = SidxLocation::Missing;
+                box_.parser.stop();
+                Ok(())
+            })
+            // A box header cut at the end of `data` leaves the location at the box start.
+            .parse(data, true, true)?;
+
+        Ok(location.replace(SidxLocation::Missing))
+    }
+
     pub fn new(box_: &mut ParsedBox, offset: u64) -> Result<Self> {
         if box_.version.is_none() {
             bail!("SIDX is a full box and should have a valid version.");
@@ -55,14 +125,14 @@ impl SidxBox {
             bail!("SIDX box has invalid timescale.");
         }
 
-        let _earliest_presentation_time;
+        let earliest_presentation_time;
         let first_offset;
 
         if version == 0 {
-            _earliest_presentation_time = reader.read_u32()? as u64;
+            earliest_presentation_time = reader.read_u32()? as u64;
             first_offset = reader.read_u32()? as u64;
         } else {
-            _earliest_presentation_time = reader.read_u64()?;
+            earliest_presentation_time = reader.read_u64()?;
             first_offset = reader.read_u64()?;
         }
 
@@ -70,8 +140,7 @@ impl SidxBox {
 
         let reference_count = reader.read_u16()?;
 
-        // Subtract the presentation time offset
-        // let mut unscaled_start_time = earliest_presentation_time;
+        let mut unscaled_start_time = earliest_presentation_time;
         let mut start_byte = offset + box_.size as u64 + first_offset;
 
         for _ in 0..reference_count {
@@ -80,32 +149,34 @@ impl SidxBox {
             let reference_type = (chunk & 0x80000000) >> 31;
             let reference_size = chunk & 0x7FFFFFFF;
 
-            let _subsegment_duration = reader.read_u32()?;
-
-            // Skipping 1 bit for |startsWithSap|, 3 bits for |sapType|, and 28 bits
-            // for |sapDelta|.
-            reader.skip(4)?;
-
-            // If |referenceType| is 1 then the reference is to another SIDX.
-            // We do not support this.
-            if reference_type == 1 {
-                bail!("Hierarchical SIDXs are not supported.");
-            }
+            let subsegment_duration = reader.read_u32()?;
 
-            // The media timestamps inside the container.
-            // let native_start_Time = unscaled_start_time as f64 / timescale as f64;
-            // let native_end_Time = (unscaled_start_time as f64 + subsegment_duration as f64) / timescale as f64;
+            // |sap| is 1 bit for |startsWithSap|, 3 bits for |sapType|, and 28 bits
+            // for |sapDeltaTime|.
+            let sap = reader.read_u32()?;
 
+            // If |referenceType| is 1 then the reference is to another SIDX,
+            // which the caller has to fetch and parse.
             references.push(SidxRange {
+                duration: subsegment_duration as f64 / timescale as f64,
                 end: start_byte + reference_size as u64 - 1,
+                is_sidx: reference_type == 1,
+                sap_delta_time: sap & 0x0FFFFFFF,
+                sap_type: ((sap >> 28) & 0x7) as u8,
                 start: start_byte,
+                start_time: unscaled_start_time as f64 / timescale as f64,
+                starts_with_sap: sap >> 31 == 1,
             });
 
-            // unscaled_start_time += subsegment_duration as u64;
+            unscaled_start_time += subsegment_duration as u64;
             start_byte += reference_size as u64;
         }
 
         box_.parser.stop();
-        Ok(Self { ranges: references })
+        Ok(Self {
+            earliest_presentation_time,
+            ranges: references,
+            timescale,
+        })
     }
 }
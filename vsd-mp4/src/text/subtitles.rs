@@ -31,6 +31,11 @@ impl Subtitles {
         self.cues.extend(cues);
     }
 
+    /// Append the cues of subtitles parsed separately, e.g. with another init segment.
+    pub fn extend(&mut self, other: Self) {
+        self.cues.extend(other.cues);
+    }
+
     pub(crate) fn fix_cues(self) -> Self {
         let mut cues: Vec<Cue> = Vec::new();
 
@@ -5,7 +5,10 @@ use std::{
     path::PathBuf,
     sync::LazyLock,
 };
-use vsd_mp4::decrypt::CencDecryptingProcessor;
+use vsd_mp4::{
+    boxes::{SidxBox, SidxLocation},
+    decrypt::CencDecryptingProcessor,
+};
 
 const VIDEO_KID: &str = "eb676abbcb345e96bbcf616630f1a3da";
 const VIDEO_KEY: &str = "100b6c20940f779a4589152b57d2dacb";
@@ -85,3 +88,76 @@ sample!(test_cbcs_multi_video, "cbcs", "multi", "video");
 sample!(test_cbcs_multi_audio, "cbcs", "multi", "audio");
 sample!(test_cbcs_single_video, "cbcs", "single", "video");
 sample!(test_cbcs_single_audio, "cbcs", "single", "audio");
+
+// ==========================================
+// SIDX Tests
+// ==========================================
+
+#[test]
+fn test_sidx_nested() -> Result<(), Box<dyn Error>> {
+    let data = fs::read(SAMPLES_DIR.join("sidx-nested/index.mp4"))?;
+
+    let SidxLocation::Found { end, start } = SidxBox::locate(&data, 0)? else {
+        return Err("SIDX box not found.".into());
+    };
+    assert_eq!((start, end), (16, 71));
+
+    let mut ranges = SidxBox::from_init(&data[start as usize..=end as usize], start)?
+        .ok_or("SIDX box not parsed.")?
+        .ranges;
+    assert!(ranges.iter().all(|x| x.is_sidx));
+
+    // Nested boxes are followed the same way vsd expands hierarchical indexes.
+    while let Some(index) = ranges.iter().position(|x| x.is_sidx) {
+        let range = ranges.remove(index);
+        let nested = SidxBox::from_init(
+            &data[range.start as usize..=range.end as usize],
+            range.start,
+        )?
+        .ok_or("Nested SIDX box not parsed.")?;
+        ranges.splice(index..index, nested.ranges);
+    }
+
+    assert_eq!(
+        ranges
+            .iter()
+            .map(|x| (x.start, x.end, x.start_time, x.duration))
+            .collect::<Vec<_>>(),
+        [
+            (128, 227, 0.0, 2.0),
+            (228, 307, 2.0, 2.0),
+            (352, 471, 4.0, 3.0)
+        ]
+    );
+    assert!(ranges.iter().all(|x| x.starts_with_sap && x.sap_type == 1));
+    Ok(())
+}
+
+#[test]
+fn test_sidx_split_window() -> Result<(), Box<dyn Error>> {
+    let data = fs::read(SAMPLES_DIR.join("sidx-nested/index.mp4"))?;
+
+    // The window ends inside the sidx box header, 4 bytes after the 16 byte ftyp box.
+    let SidxLocation::Continue(next) = SidxBox::locate(&data[..20], 0)? else {
+        return Err("Partial SIDX box header should need more data.".into());
+    };
+    assert_eq!(next, 16);
+
+    let SidxLocation::Found { end, start } = SidxBox::locate(&data[next as usize..], next)? else {
+        return Err("SIDX box not found in the next window.".into());
+    };
+    assert_eq!((start, end), (16, 71));
+
+    // A window ending inside the ftyp box header needs more data from the start.
+    assert!(matches!(
+        SidxBox::locate(&data[..4], 0)?,
+        SidxLocation::Continue(0)
+    ));
+
+    // A header is enough to locate the box, its payload may be cut.
+    assert!(matches!(
+        SidxBox::locate(&data[..40], 0)?,
+        SidxLocation::Found { start: 16, end: 71 }
+    ));
+    Ok(())
+}
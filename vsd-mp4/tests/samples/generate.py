@@ -1,4 +1,5 @@
 import os
+import struct
 import subprocess
 
 VIDEO_KID = "eb676abbcb345e96bbcf616630f1a3da"
@@ -40,6 +41,32 @@ def run_packager(scheme, mode):
 
     run_command(cmd)
 
+def mp4_box(typ, payload):
+    return struct.pack(">I", 8 + len(payload)) + typ + payload
+
+def sidx_box(earliest_presentation_time, references):
+    # version 0, reference_ID 1, timescale 1000, first_offset 0
+    payload = struct.pack(">IIIIIHH", 0, 1, 1000, earliest_presentation_time, 0, 0, len(references))
+
+    for reference_type, size, duration in references:
+        # starts_with_sap 1, sap_type 1, sap_delta_time 0
+        payload += struct.pack(">III", (reference_type << 31) | size, duration, 0x90000000)
+
+    return mp4_box(b"sidx", payload)
+
+def write_nested_sidx():
+    print("Processing sidx-nested")
+
+    media = [mp4_box(b"mdat", bytes(size - 8)) for size in (100, 80, 120)]
+    nested_a = sidx_box(0, [(0, 100, 2000), (0, 80, 2000)]) + media[0] + media[1]
+    nested_b = sidx_box(4000, [(0, 120, 3000)]) + media[2]
+    top = sidx_box(0, [(1, len(nested_a), 4000), (1, len(nested_b), 3000)])
+
+    os.makedirs("sidx-nested", exist_ok=True)
+
+    with open("sidx-nested/index.mp4", "wb") as f:
+        f.write(mp4_box(b"ftyp", b"isom" + bytes(4)) + top + nested_a + nested_b)
+
 def main():
     print("Generating test.mp4")
 
@@ -58,6 +85,8 @@ def main():
         for mode in modes:
             run_packager(scheme, mode)
 
+    write_nested_sidx()
+
     if os.path.exists("test.mp4"):
         os.remove("test.mp4")
 
@@ -180,10 +180,10 @@ fn write_option(buffer: &mut String, arg: &Arg) {
         help.push_str(&format!("<br>*Possible values:* `{}`", values.join("`, `")));
     }
 
-    if let Some(default) = arg.get_default_values().first() {
-        if !arg.is_hide_default_value_set() {
-            help.push_str(&format!("<br>*Default:* `{}`", default.to_string_lossy()));
-        }
+    if let Some(default) = arg.get_default_values().first()
+        && !arg.is_hide_default_value_set()
+    {
+        help.push_str(&format!("<br>*Default:* `{}`", default.to_string_lossy()));
     }
 
     let help = help.replace('|', "\\|").replace('\n', "<br>");
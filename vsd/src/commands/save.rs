@@ -1,26 +1,32 @@
-use crate::{Downloader, cookie::Cookies};
-use anyhow::{Result, bail};
+use crate::{Downloader, cookie::Cookies, logger};
+use anyhow::{Result, anyhow, bail};
 use clap::Args;
 use reqwest::{
     Client, Proxy, Url,
     cookie::Jar,
     header::{HeaderMap, HeaderName, HeaderValue},
 };
-use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, atomic::Ordering},
+    time::Duration,
+};
 use tokio::fs;
 
 /// Download streams from DASH or HLS playlist.
 #[derive(Args, Clone, Debug)]
 pub struct Save {
-    /// HTTP(S):// | .M3U8 | .MPD
+    /// HTTP(S):// | FILE:// | .M3U8 | .MPD
     #[arg(required = true)]
     pub input: String,
 
     /// Base URL for resolving relative segment paths.
     ///
-    /// Required for local playlist files. For remote playlists,
-    /// the final redirected URL is used by default.
-    #[arg(long)]
+    /// Accepts `http(s)://` and `file://` URLs or a local directory.
+    /// For local playlist files, the playlist's own directory is used by default.
+    /// For remote playlists, the final redirected URL is used by default.
+    #[arg(long, value_parser = Self::parse_base_url)]
     pub base_url: Option<Url>,
 
     /// Working directory for temporary segment files.
@@ -32,6 +38,7 @@ pub struct Save {
     /// Mux downloaded streams into a video container using ffmpeg (`.mp4`, `.mkv`, etc.).
     ///
     /// Overwrites existing files and deletes intermediate stream files after muxing.
+    /// Use `-` to write a single stream as-is, or multiple streams muxed as matroska, to stdout.
     #[arg(short, long)]
     pub output: Option<PathBuf>,
 
@@ -50,7 +57,8 @@ pub struct Save {
     )]
     pub subs_codec: String,
 
-    /// Enable interactive stream selection with styled prompts.
+    /// Enable interactive stream selection with a full screen picker.
+    /// Streams can be filtered by typing and Tab toggles a preview of the mux command.
     #[arg(
         short,
         long,
@@ -80,13 +88,31 @@ pub struct Save {
         |> all: select all streams.\n\
         |> skip: skip all streams or select inverter.\n\
         |> 1,2: indices obtained by --list-streams flag.\n\
-        |> 1080p,1280x720: stream resolution.\n\
-        |> en,fr: stream language.\n\n\
+        |> 1080p,1280x720: stream resolution, 1080p also matches 1920x800 or 1440x1080.\n\
+        |> <=1080p,~720p: best stream up to a resolution or the nearest one.\n\
+        Unmatched resolutions fall back to the closest lower quality.\n\
+        |> en,fre,pt-BR: stream language (iso 639 or bcp-47), other regions are used as fallback.\n\
+        |> best-per-lang,top2: best stream of every language or the best n streams.\n\
+        |> en,de:best: best stream of each language instead of the variant group ones.\n\
+        |> codec:hvc1: streams with a codec (prefix or alias like hevc, avc, aac, eac3).\n\
+        |> bw<=5M,fps>=50,height<=720,width>=1280,ch>=6: attribute predicates\n\
+        (operators: <, <=, =, >=, >) all of which must match.\n\
+        |> hdr,sdr,dv: dynamic range (pq or hlg) and dolby vision video streams.\n\
+        |> main,forced,description,commentary,sdh,default: stream roles, prefix with ! to exclude.\n\n\
+        `id={}` selects streams by the ids obtained by --list-streams flag, which unlike\n\
+        indices don't change between runs or with expiring query tokens, so they can be\n\
+        saved in scripts and batch configs. It can be combined with other sections.\n\n\
         EXAMPLES:\n\n\
         |> 1,2,3 (indices 1, 2, and 3)\n\
+        |> id=ab12cd3,ef45678 (streams ab12cd3 and ef45678)\n\
         |> v=skip:a=skip:s=all (all sub streams)\n\
         |> a:en:s=en (prefer en lang)\n\
-        |> v=1080p:a=all:s=skip (1080p with all aud streams)\n"
+        |> v=1080p:a=all:s=skip (1080p with all aud streams)\n\
+        |> v=codec:hvc1,bw<=5M:a=codec:ec-3,ch>=6 (hevc under 5 Mbps with 5.1 eac3)\n\
+        |> v=sdr,best (best stream for devices without hdr support)\n\
+        |> a=en,de:best (best en and de aud streams)\n\
+        |> a=best-per-lang,top3 (best aud streams of up to 3 languages)\n\
+        |> a=en,!description:s=en,forced (en dub without audio description, forced en subs)\n"
     )]
     pub select_streams: String,
 
@@ -118,12 +144,37 @@ pub struct Save {
     #[arg(long, help_heading = "Decrypt Options")]
     pub no_decrypt: bool,
 
+    /// Fill gap segments (`EXT-X-GAP`) with silence and repeated frames when muxing.
+    ///
+    /// Gap segments are never downloaded. Streams having them are re-encoded with ffmpeg.
+    #[arg(long, help_heading = "Download Options")]
+    pub fill_gaps: bool,
+
+    /// Start downloading at the playlist's preferred start point (`EXT-X-START`).
+    #[arg(long, help_heading = "Download Options")]
+    pub start_at_offset: bool,
+
     /// Skip segment merging and keep individual files.
     ///
     /// Ignores `--output` when enabled.
     #[arg(long, help_heading = "Download Options")]
     pub no_merge: bool,
 
+    /// Command to run after a successful download and mux.
+    ///
+    /// `{input}` and `{output}` placeholders expand to the quoted `VSD_INPUT` and `VSD_OUTPUT`
+    /// variables, so they must not be quoted again. Job details are available as
+    /// `VSD_INPUT`, `VSD_OUTPUT`, `VSD_STREAMS` (json), `VSD_ELAPSED` (seconds)
+    /// and `VSD_BYTES` environment variables. The hook's exit status is shown in the final summary.
+    #[arg(long, value_name = "CMD", help_heading = "Download Options")]
+    pub exec: Option<String>,
+
+    /// Command to run when the download fails.
+    ///
+    /// Same as `--exec`, with the error message available as `VSD_ERROR` environment variable.
+    #[arg(long, value_name = "CMD", help_heading = "Download Options")]
+    pub on_error: Option<String>,
+
     /// Maximum retry attempts per segment.
     #[arg(long, help_heading = "Download Options", default_value_t = 10)]
     pub retries: u8,
@@ -134,6 +185,17 @@ pub struct Save {
 }
 
 impl Save {
+    fn parse_base_url(s: &str) -> Result<Url> {
+        let path = Path::new(s);
+
+        if path.is_dir() {
+            return Url::from_directory_path(path.canonicalize()?)
+                .map_err(|_| anyhow!("Expected a local directory but found '{}'.", s));
+        }
+
+        Ok(s.parse()?)
+    }
+
     fn parse_header(s: &str) -> Result<(HeaderName, HeaderValue)> {
         if let Some((k, v)) = s.split_once(':') {
             Ok((k.trim().parse()?, v.trim().parse()?))
@@ -198,6 +260,8 @@ impl Save {
             .keys(self.keys)
             .skip_decrypt(self.no_decrypt)
             .skip_merge(self.no_merge)
+            .fill_gaps(self.fill_gaps)
+            .start_at_offset(self.start_at_offset)
             .max_retries(self.retries)
             .max_threads(self.threads);
 
@@ -208,11 +272,20 @@ impl Save {
             dl = dl.directory(directory);
         }
         if let Some(output) = self.output {
+            if output.as_os_str() == "-" {
+                logger::STDERR.store(true, Ordering::SeqCst);
+            }
             dl = dl.output(output);
         }
         if let Some(query) = self.query {
             dl = dl.query(&query);
         }
+        if let Some(cmd) = self.exec {
+            dl = dl.exec(cmd);
+        }
+        if let Some(cmd) = self.on_error {
+            dl = dl.on_error(cmd);
+        }
         if self.interactive {
             dl = dl.interactive(false);
         } else if self.interactive_raw {
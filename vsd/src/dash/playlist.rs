@@ -6,64 +6,60 @@
 
 */
 
-use super::{DashUrl, Template};
+use super::{DashUrl, Template, webm};
 use crate::{
     playlist::{
-        Key, KeyMethod, Map, MasterPlaylist, MediaPlaylist, MediaType, PlaylistType, Range, Segment,
+        Groups, Key, KeyMethod, Map, MasterPlaylist, MediaPlaylist, MediaType, PlaylistType, Range,
+        Segment, VideoRange,
     },
+    request::Request,
     utils,
 };
 use anyhow::{Result, anyhow, bail};
-use dash_mpd::MPD;
-use reqwest::{Client, Url, header};
+use dash_mpd::{AdaptationSet, MPD, Period, Representation, SegmentTimeline};
+use reqwest::{Client, Url};
 use std::collections::HashMap;
-use vsd_mp4::boxes::SidxBox;
+use vsd_mp4::boxes::{SidxBox, SidxLocation, SidxRange};
 
 pub(crate) fn parse_as_master(playlist: &MPD, base_url: &str) -> MasterPlaylist {
     let mut streams = vec![];
+    let mut tracks: Vec<(usize, usize, usize)> = vec![];
 
-    if let Some(period) = playlist.periods.first() {
-        let period_index = 0;
+    for (period_index, period) in playlist.periods.iter().enumerate() {
         for (adaptation_index, adaptation_set) in period.adaptations.iter().enumerate() {
             for (representation_index, representation) in
                 adaptation_set.representations.iter().enumerate()
             {
+                // Tracks continuing from an earlier period are stitched together by push_segments.
+                if tracks.iter().any(|&(p, a, r)| {
+                    let previous = &playlist.periods[p].adaptations[a];
+                    p < period_index
+                        && find_track(period, previous, &previous.representations[r])
+                            == Some((adaptation_index, representation_index))
+                }) {
+                    continue;
+                }
+
+                tracks.push((period_index, adaptation_index, representation_index));
+
                 // https://dashif.org/codecs/introduction
                 let codecs = representation
                     .codecs
                     .clone()
                     .or(adaptation_set.codecs.clone());
-
-                let mime_type = representation
-                    .mimeType
-                    .clone()
-                    .or(adaptation_set.mimeType.clone())
-                    .or(representation.contentType.clone())
-                    .or(adaptation_set.contentType.clone());
-
-                let mut media_type = if let Some(mime_type) = &mime_type {
-                    match mime_type.as_str() {
-                        "application/ttml+xml" | "application/x-sami" => MediaType::Subtitles,
-                        x if x.starts_with("audio") => MediaType::Audio,
-                        x if x.starts_with("text") => MediaType::Subtitles,
-                        x if x.starts_with("video") => MediaType::Video,
-                        _ => MediaType::Undefined,
-                    }
-                } else {
-                    MediaType::Undefined
-                };
-
-                if media_type == MediaType::Undefined
-                    && let Some(codecs) = &codecs
-                {
-                    media_type = match codecs.as_str() {
-                        "wvtt" | "stpp" => MediaType::Subtitles,
-                        x if x.starts_with("stpp.") => MediaType::Subtitles,
-                        _ => media_type,
-                    };
-                }
+                let (media_type, mime_type) = media_type(adaptation_set, representation);
+                let dolby_vision = codecs
+                    .as_deref()
+                    .into_iter()
+                    .chain(representation.scte214_supplemental_codecs.as_deref())
+                    .chain(adaptation_set.scte214_supplemental_codecs.as_deref())
+                    .find_map(MediaPlaylist::find_dolby_vision);
+                let mut roles = roles(adaptation_set);
+                let forced = roles.iter().any(|x| x == "forced-subtitle");
+                roles.retain(|x| x != "forced-subtitle");
 
                 streams.push(MediaPlaylist {
+                    autoselect: false,
                     bandwidth: representation.bandwidth,
                     channels: representation
                         .AudioChannelConfiguration
@@ -76,9 +72,12 @@ pub(crate) fn parse_as_master(playlist: &MPD, base_url: &str) -> MasterPlaylist
                             .and_then(|x| x.value.as_ref().map(|y| y.parse::<f32>().ok()))
                             .flatten()),
                     codecs,
+                    default: false,
+                    dolby_vision,
                     extension: mime_type
                         .as_ref()
                         .and_then(|x| x.split_once('/').map(|x| x.1.to_owned())),
+                    forced,
                     frame_rate: if representation.frameRate.is_some() {
                         parse_frame_rate(&representation.frameRate)
                     } else if adaptation_set.frameRate.is_some() {
@@ -86,11 +85,24 @@ pub(crate) fn parse_as_master(playlist: &MPD, base_url: &str) -> MasterPlaylist
                     } else {
                         None
                     },
-                    id: utils::gen_id(
-                        base_url,
-                        &DashUrl::new(period_index, adaptation_index, representation_index)
-                            .to_string(),
-                    ),
+                    group_id: None,
+                    groups: Groups::default(),
+                    // Element ids survive reordering, positions are only a fallback.
+                    id: match &representation.id {
+                        Some(id) => utils::gen_id(
+                            base_url,
+                            &format!(
+                                "{}/{}",
+                                adaptation_set.id.as_deref().unwrap_or_default(),
+                                id
+                            ),
+                        ),
+                        None => utils::gen_id(
+                            base_url,
+                            &DashUrl::new(period_index, adaptation_index, representation_index)
+                                .to_string(),
+                        ),
+                    },
                     i_frame: false, // Cannot be comment here
                     language: adaptation_set.lang.clone(),
                     live: if let Some(mpdtype) = &playlist.mpdtype {
@@ -98,6 +110,7 @@ pub(crate) fn parse_as_master(playlist: &MPD, base_url: &str) -> MasterPlaylist
                     } else {
                         false
                     },
+                    low_latency: false,
                     media_sequence: 0,
                     media_type,
                     playlist_type: PlaylistType::Dash,
@@ -108,15 +121,28 @@ pub(crate) fn parse_as_master(playlist: &MPD, base_url: &str) -> MasterPlaylist
                     } else {
                         None
                     },
+                    roles,
                     segments: Vec::new(), // Cannot be comment here
+                    start_offset: None,
                     uri: DashUrl::new(period_index, adaptation_index, representation_index)
                         .to_string(),
+                    variables: HashMap::new(),
+                    video_range: video_range(adaptation_set, representation),
                 });
             }
         }
     }
 
+    let duration = playlist
+        .periods
+        .len()
+        .checked_sub(1)
+        .map(|x| period_timing(playlist, x))
+        .map(|(start, duration)| start + duration)
+        .filter(|x| *x > 0.0);
+
     MasterPlaylist {
+        duration,
         playlist_type: PlaylistType::Dash,
         uri: base_url.to_owned(),
         streams,
@@ -131,409 +157,737 @@ pub(crate) async fn push_segments(
     query: &Vec<(String, String)>,
 ) -> Result<()> {
     let location = stream.uri.parse::<DashUrl>().map_err(|x| anyhow!(x))?;
+    let adaptation_set = playlist
+        .periods
+        .get(location.period)
+        .and_then(|x| x.adaptations.get(location.adaptation_set))
+        .ok_or_else(|| anyhow!("{} is not a valid dash location.", stream.uri))?;
+    let representation = adaptation_set
+        .representations
+        .get(location.representation)
+        .ok_or_else(|| anyhow!("{} is not a valid dash location.", stream.uri))?;
+
+    // The track continues in later periods, possibly under different indices.
+    for (period_index, period) in playlist.periods.iter().enumerate().skip(location.period) {
+        let track = if period_index == location.period {
+            Some((location.adaptation_set, location.representation))
+        } else {
+            find_track(period, adaptation_set, representation)
+        };
+
+        if let Some((adaptation_index, representation_index)) = track {
+            stream.segments.extend(
+                period_segments(
+                    playlist,
+                    period_index,
+                    adaptation_index,
+                    representation_index,
+                    client,
+                    base_url,
+                    query,
+                )
+                .await?,
+            );
+        }
+    }
 
-    for period in playlist.periods.iter() {
-        for (adaptation_index, adaptation_set) in period.adaptations.iter().enumerate() {
-            for (representation_index, representation) in
-                adaptation_set.representations.iter().enumerate()
+    stream.uri = base_url.to_owned();
+    Ok(())
+}
+
+async fn period_segments(
+    playlist: &MPD,
+    period_index: usize,
+    adaptation_index: usize,
+    representation_index: usize,
+    client: &Client,
+    base_url: &str,
+    query: &Vec<(String, String)>,
+) -> Result<Vec<Segment>> {
+    let period = &playlist.periods[period_index];
+    let adaptation_set = &period.adaptations[adaptation_index];
+    let representation = &adaptation_set.representations[representation_index];
+    let (period_start_secs, period_duration_secs) = period_timing(playlist, period_index);
+    let mut segments = Vec::new();
+
+    let mut base_url = base_url.parse::<Url>().unwrap();
+
+    if let Some(mpd_baseurl) = playlist.base_url.first().map(|x| x.base.as_ref()) {
+        base_url = base_url.join(mpd_baseurl)?;
+    }
+
+    if let Some(period_baseurl) = period.BaseURL.first().map(|x| x.base.as_ref()) {
+        base_url = base_url.join(period_baseurl)?;
+    }
+
+    if let Some(adaptation_set_baseurl) = adaptation_set.BaseURL.first().map(|x| x.base.as_ref()) {
+        base_url = base_url.join(adaptation_set_baseurl)?;
+    }
+
+    if let Some(representation_baseurl) = representation.BaseURL.first().map(|x| x.base.as_ref()) {
+        base_url = base_url.join(representation_baseurl)?;
+    }
+
+    let mut init_map = None;
+
+    let rid = if let Some(id) = &representation.id {
+        id.to_owned()
+    } else {
+        bail!("missing @id on representation node.");
+    };
+
+    let mut template_vars = HashMap::from([("RepresentationID".to_owned(), rid)]);
+
+    if let Some(bandwidth) = &representation.bandwidth {
+        template_vars.insert("Bandwidth".to_owned(), bandwidth.to_string());
+    }
+
+    let mut template = Template::new(template_vars);
+
+    // Now the 6 possible addressing modes:
+    // (1.1) AdaptationSet>SegmentList
+    // (1.2) Representation>SegmentList
+    // ( 2 ) SegmentTemplate+SegmentTimeline
+    // ( 3 ) SegmentTemplate@duration
+    // ( 4 ) SegmentTemplate@index
+    // ( 5 ) SegmentBase@indexRange
+    // ( 6 ) Plain BaseURL
+
+    // Though SegmentBase and SegmentList addressing modes are supposed to be
+    // mutually exclusive, some manifests in the wild use both. So we try to work
+    // around the brokenness.
+
+    // (1.1) AdaptationSet>SegmentList
+    if let Some(segment_list) = &adaptation_set.SegmentList {
+        if let Some(initialization) = &segment_list.Initialization {
+            let byte_range = parse_range(&initialization.range);
+
+            if let Some(source_url) = &initialization.sourceURL {
+                init_map = Some(Map {
+                    range: byte_range,
+                    uri: base_url.join(&template.resolve(source_url))?.to_string(),
+                });
+            } else {
+                init_map = Some(Map {
+                    range: byte_range,
+                    uri: base_url.to_string(),
+                });
+            }
+        }
+
+        let duration =
+            segment_list.duration.unwrap_or(0) as f32 / segment_list.timescale.unwrap_or(1) as f32;
+
+        for segment_url in &segment_list.segment_urls {
+            // We are ignoring SegmentURL@indexRange
+            let byte_range = parse_range(&segment_url.mediaRange);
+
+            if let Some(media) = &segment_url.media {
+                segments.push(Segment {
+                    duration,
+                    range: byte_range,
+                    uri: base_url.join(media)?.to_string(),
+                    ..Default::default()
+                });
+            } else if !adaptation_set.BaseURL.is_empty() {
+                segments.push(Segment {
+                    duration,
+                    range: byte_range,
+                    uri: base_url.to_string(),
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    // (1.2) Representation>SegmentList
+    if let Some(segment_list) = &representation.SegmentList {
+        if let Some(initialization) = &segment_list.Initialization {
+            let byte_range = parse_range(&initialization.range);
+
+            if let Some(source_url) = &initialization.sourceURL {
+                init_map = Some(Map {
+                    range: byte_range,
+                    uri: base_url.join(&template.resolve(source_url))?.to_string(),
+                });
+            } else {
+                init_map = Some(Map {
+                    range: byte_range,
+                    uri: base_url.to_string(),
+                });
+            }
+        }
+
+        let duration =
+            segment_list.duration.unwrap_or(0) as f32 / segment_list.timescale.unwrap_or(1) as f32;
+
+        for segment_url in &segment_list.segment_urls {
+            // We are ignoring SegmentURL@indexRange
+            let byte_range = parse_range(&segment_url.mediaRange);
+
+            if let Some(media) = &segment_url.media {
+                segments.push(Segment {
+                    duration,
+                    range: byte_range,
+                    uri: base_url.join(media)?.to_string(),
+                    ..Default::default()
+                });
+            } else if !representation.BaseURL.is_empty() {
+                segments.push(Segment {
+                    duration,
+                    range: byte_range,
+                    uri: base_url.to_string(),
+                    ..Default::default()
+                });
+            }
+        }
+    } else if representation.SegmentTemplate.is_some() || adaptation_set.SegmentTemplate.is_some() {
+        let segment_template = representation
+            .SegmentTemplate
+            .as_ref()
+            .or(adaptation_set.SegmentTemplate.as_ref())
+            .unwrap();
+
+        if let Some(initialization) = &segment_template.initialization {
+            init_map = Some(Map {
+                range: None,
+                uri: base_url
+                    .join(&template.resolve(initialization))?
+                    .to_string(),
+            });
+        }
+
+        // (2) SegmentTemplate+SegmentTimeline (explicit addressing)
+        if let Some(segment_timeline) = &segment_template.SegmentTimeline {
+            if segment_template.media.is_none() {
+                bail!("SegmentTimeline without a media attribute.");
+            }
+
+            let media = template.resolve(segment_template.media.as_ref().unwrap());
+            let start_number = segment_template.startNumber.unwrap_or(1);
+            let timescale = segment_template.timescale.unwrap_or(1);
+            let presentation_time_offset = segment_template.presentationTimeOffset.unwrap_or(0);
+            let period_end = presentation_time_offset
+                + (period_duration_secs as f64 * timescale as f64).round() as u64;
+
+            for (number, (time, duration)) in
+                (start_number..).zip(expand_timeline(segment_timeline, period_end))
             {
-                if adaptation_index == location.adaptation_set
-                    && representation_index == location.representation
-                {
-                    let mut period_duration_secs = 0.0;
+                template.insert("Time", time.to_string());
+                template.insert("Number", number.to_string());
+
+                segments.push(Segment {
+                    duration: duration as f32 / timescale as f32,
+                    start: Some(
+                        period_start_secs as f64
+                            + (time as f64 - presentation_time_offset as f64) / timescale as f64,
+                    ),
+                    uri: base_url.join(&template.resolve(&media))?.to_string(),
+                    ..Default::default()
+                });
+            }
+        } else if let Some(media) = &segment_template.media {
+            // (3) SegmentTemplate@duration || (4) SegmentTemplate@index (simple addressing)
+            let mut segment_duration = -1.0;
+            let media = template.resolve(media);
+            let timescale = segment_template.timescale.unwrap_or(1) as f32;
+
+            if let Some(x) = segment_template.duration {
+                segment_duration = x as f32 / timescale;
+            }
 
-                    if let Some(duration) = &playlist.mediaPresentationDuration {
-                        period_duration_secs = duration.as_secs_f32();
-                    }
+            if segment_duration < 0.0 {
+                bail!("Representation is missing SegmentTemplate@duration attribute.");
+            }
 
-                    if let Some(duration) = &period.duration {
-                        period_duration_secs = duration.as_secs_f32();
-                    }
+            let number = segment_template.startNumber.unwrap_or(1) as i64;
+            let total_number = (period_duration_secs / segment_duration).ceil() as i64;
+
+            // // For a live manifest (dynamic MPD), we look at the time elapsed since now
+            // // and the mpd.availabilityStartTime to determine the correct value for
+            // // startNumber, based on duration and timescale. The latest available
+            // // segment is numbered
+            // //
+            // //    LSN = floor((now - (availabilityStartTime+PST))/segmentDuration + startNumber - 1)
+
+            // // https://dashif.org/Guidelines-TimingModel/Timing-Model.pdf
+            // // To be more precise, any LeapSecondInformation should be added to the availabilityStartTime.
+            // if mpd_is_dynamic(mpd) {
+            //     if let Some(start_time) = mpd.availabilityStartTime {
+            //         let elapsed = Utc::now()
+            //             .signed_duration_since(start_time)
+            //             .as_seconds_f64()
+            //             / segment_duration;
+            //         number = (elapsed + number as f64 - 1f64).floor() as u64;
+            //     } else {
+            //         return Err(DashMpdError::UnhandledMediaStream(
+            //             "dynamic manifest is missing @availabilityStartTime"
+            //                 .to_string(),
+            //         ));
+            //     }
+            // }
+
+            for number in number..(number + total_number) {
+                template.insert("Number", number.to_string());
+
+                segments.push(Segment {
+                    duration: segment_duration,
+                    uri: base_url.join(&template.resolve(&media))?.to_string(),
+                    ..Default::default()
+                });
+            }
+        }
+    } else if let Some(segment_base) = &representation.SegmentBase {
+        // (5) SegmentBase@indexRange (located from the mp4 boxes when missing)
+        if let Some(initialization) = &segment_base.Initialization {
+            let byte_range = parse_range(&initialization.range);
+
+            if let Some(source_url) = &initialization.sourceURL {
+                init_map = Some(Map {
+                    range: byte_range,
+                    uri: base_url.join(&template.resolve(source_url))?.to_string(),
+                });
+            } else {
+                init_map = Some(Map {
+                    range: byte_range,
+                    uri: base_url.to_string(),
+                });
+            }
+        }
 
-                    let mut base_url = base_url.parse::<Url>().unwrap();
+        let is_webm = media_type(adaptation_set, representation)
+            .1
+            .is_some_and(|x| x.ends_with("/webm"));
+        let index_range = match parse_range(&segment_base.indexRange) {
+            Some(index_range) => Some(index_range),
+            None if is_webm => None,
+            None => locate_sidx(client, &base_url, query).await?,
+        };
+
+        // WebM indexes clusters with a Cues element instead of a sidx box.
+        let cue_segments = if is_webm {
+            let init_range = init_map.as_ref().and_then(|x| x.range.as_ref());
+            webm::cue_segments(client, &base_url, query, init_range, index_range.as_ref()).await?
+        } else {
+            None
+        };
+
+        if let Some((init_range, cue_segments)) = cue_segments {
+            init_map
+                .get_or_insert_with(|| Map {
+                    range: None,
+                    uri: base_url.to_string(),
+                })
+                .range = Some(init_range);
+
+            for mut segment in cue_segments {
+                segment.start = segment.start.map(|x| x + period_start_secs as f64);
+                segments.push(segment);
+            }
+        } else if !is_webm && let Some(index_range) = index_range {
+            let map = init_map.get_or_insert_with(|| Map {
+                range: None,
+                uri: base_url.to_string(),
+            });
+            map.range = Some(Range {
+                end: index_range.end,
+                start: 0,
+            });
+
+            let presentation_time_offset = segment_base.presentationTimeOffset.unwrap_or(0) as f64
+                / segment_base.timescale.unwrap_or(1) as f64;
+
+            for range in sidx_ranges(client, &base_url, query, index_range).await? {
+                segments.push(Segment {
+                    duration: range.duration as f32,
+                    range: Some(Range {
+                        end: range.end,
+                        start: range.start,
+                    }),
+                    start: Some(
+                        period_start_secs as f64 + range.start_time - presentation_time_offset,
+                    ),
+                    uri: base_url.to_string(),
+                    ..Default::default()
+                });
+            }
+        } else {
+            segments.push(Segment {
+                uri: base_url.to_string(),
+                ..Default::default()
+            });
+        }
+    } else if segments.is_empty() && !representation.BaseURL.is_empty() {
+        // (6) Plain BaseURL
+        segments.push(Segment {
+            duration: period_duration_secs,
+            uri: base_url.to_string(),
+            ..Default::default()
+        });
+    }
 
-                    if let Some(mpd_baseurl) = playlist.base_url.first().map(|x| x.base.as_ref()) {
-                        base_url = base_url.join(mpd_baseurl)?;
-                    }
+    if segments.is_empty() {
+        bail!("no usable addressing mode identified for representation.");
+    }
 
-                    if let Some(period_baseurl) = period.BaseURL.first().map(|x| x.base.as_ref()) {
-                        base_url = base_url.join(period_baseurl)?;
-                    }
+    // Segments without explicit timing follow each other from the period start.
+    let mut start = period_start_secs as f64;
 
-                    if let Some(adaptation_set_baseurl) =
-                        adaptation_set.BaseURL.first().map(|x| x.base.as_ref())
-                    {
-                        base_url = base_url.join(adaptation_set_baseurl)?;
-                    }
+    for segment in &mut segments {
+        start = *segment.start.get_or_insert(start) + segment.duration as f64;
+    }
 
-                    if let Some(representation_baseurl) =
-                        representation.BaseURL.first().map(|x| x.base.as_ref())
-                    {
-                        base_url = base_url.join(representation_baseurl)?;
-                    }
+    if let Some(first_segment) = segments.get_mut(0) {
+        let mut encryption_type = KeyMethod::None;
+        let mut default_kid = None;
 
-                    let mut init_map = None;
+        for content_protection in &representation.ContentProtection {
+            if default_kid.is_none() && content_protection.default_KID.is_some() {
+                default_kid = content_protection.default_KID.clone();
+            }
 
-                    let rid = if let Some(id) = &representation.id {
-                        id.to_owned()
-                    } else {
-                        bail!("missing @id on representation node.");
-                    };
-
-                    let mut template_vars = HashMap::from([("RepresentationID".to_owned(), rid)]);
-
-                    if let Some(bandwidth) = &representation.bandwidth {
-                        template_vars.insert("Bandwidth".to_owned(), bandwidth.to_string());
-                    }
-
-                    let mut template = Template::new(template_vars);
-
-                    // Now the 6 possible addressing modes:
-                    // (1.1) AdaptationSet>SegmentList
-                    // (1.2) Representation>SegmentList
-                    // ( 2 ) SegmentTemplate+SegmentTimeline
-                    // ( 3 ) SegmentTemplate@duration
-                    // ( 4 ) SegmentTemplate@index
-                    // ( 5 ) SegmentBase@indexRange
-                    // ( 6 ) Plain BaseURL
-
-                    // Though SegmentBase and SegmentList addressing modes are supposed to be
-                    // mutually exclusive, some manifests in the wild use both. So we try to work
-                    // around the brokenness.
-
-                    // (1.1) AdaptationSet>SegmentList
-                    if let Some(segment_list) = &adaptation_set.SegmentList {
-                        if let Some(initialization) = &segment_list.Initialization {
-                            let byte_range = parse_range(&initialization.range);
-
-                            if let Some(source_url) = &initialization.sourceURL {
-                                init_map = Some(Map {
-                                    range: byte_range,
-                                    uri: base_url.join(&template.resolve(source_url))?.to_string(),
-                                });
-                            } else {
-                                init_map = Some(Map {
-                                    range: byte_range,
-                                    uri: base_url.to_string(),
-                                });
-                            }
-                        }
-
-                        for segment_url in &segment_list.segment_urls {
-                            // We are ignoring SegmentURL@indexRange
-                            let byte_range = parse_range(&segment_url.mediaRange);
-
-                            if let Some(media) = &segment_url.media {
-                                stream.segments.push(Segment {
-                                    range: byte_range,
-                                    uri: base_url.join(media)?.to_string(),
-                                    ..Default::default()
-                                });
-                            } else if !adaptation_set.BaseURL.is_empty() {
-                                stream.segments.push(Segment {
-                                    range: byte_range,
-                                    uri: base_url.to_string(),
-                                    ..Default::default()
-                                });
-                            }
-                        }
-                    }
-
-                    // (1.2) Representation>SegmentList
-                    if let Some(segment_list) = &representation.SegmentList {
-                        if let Some(initialization) = &segment_list.Initialization {
-                            let byte_range = parse_range(&initialization.range);
-
-                            if let Some(source_url) = &initialization.sourceURL {
-                                init_map = Some(Map {
-                                    range: byte_range,
-                                    uri: base_url.join(&template.resolve(source_url))?.to_string(),
-                                });
-                            } else {
-                                init_map = Some(Map {
-                                    range: byte_range,
-                                    uri: base_url.to_string(),
-                                });
-                            }
-                        }
-
-                        for segment_url in &segment_list.segment_urls {
-                            // We are ignoring SegmentURL@indexRange
-                            let byte_range = parse_range(&segment_url.mediaRange);
-
-                            if let Some(media) = &segment_url.media {
-                                stream.segments.push(Segment {
-                                    range: byte_range,
-                                    uri: base_url.join(media)?.to_string(),
-                                    ..Default::default()
-                                });
-                            } else if !representation.BaseURL.is_empty() {
-                                stream.segments.push(Segment {
-                                    range: byte_range,
-                                    uri: base_url.to_string(),
-                                    ..Default::default()
-                                });
-                            }
-                        }
-                    } else if representation.SegmentTemplate.is_some()
-                        || adaptation_set.SegmentTemplate.is_some()
-                    {
-                        let segment_template = representation
-                            .SegmentTemplate
-                            .as_ref()
-                            .or(adaptation_set.SegmentTemplate.as_ref())
-                            .unwrap();
-
-                        if let Some(initialization) = &segment_template.initialization {
-                            init_map = Some(Map {
-                                range: None,
-                                uri: base_url
-                                    .join(&template.resolve(initialization))?
-                                    .to_string(),
-                            });
-                        }
-
-                        // (2) SegmentTemplate+SegmentTimeline (explicit addressing)
-                        if let Some(segment_timeline) = &segment_template.SegmentTimeline {
-                            if segment_template.media.is_none() {
-                                bail!("SegmentTimeline without a media attribute.");
-                            }
-
-                            let media = template.resolve(segment_template.media.as_ref().unwrap());
-                            let mut number = segment_template.startNumber.unwrap_or(1);
-                            let mut segment_time = 0;
-                            let timescale = segment_template.timescale.unwrap_or(1) as f32;
-
-                            for s in &segment_timeline.segments {
-                                if let Some(t) = s.t {
-                                    segment_time = t;
-                                }
-
-                                template.insert("Time", segment_time.to_string());
-                                template.insert("Number", number.to_string());
-
-                                stream.segments.push(Segment {
-                                    duration: s.d as f32 / timescale,
-                                    uri: base_url.join(&template.resolve(&media))?.to_string(),
-                                    ..Default::default()
-                                });
-
-                                number += 1;
-
-                                if let Some(r) = s.r {
-                                    let mut count = 0;
-                                    // FIXME - Perhaps we also need to account for startTime?
-                                    let end_time = period_duration_secs * timescale;
-
-                                    loop {
-                                        count += 1;
-                                        // Exit from the loop after @r iterations (if @r is
-                                        // positive). A negative value of the @r attribute indicates
-                                        // that the duration indicated in @d attribute repeats until
-                                        // the start of the next S element, the end of the Period or
-                                        // until the next MPD update.
-                                        if r >= 0 {
-                                            if count > r {
-                                                break;
-                                            }
-                                        } else if segment_time as f32 > end_time {
-                                            break;
-                                        }
-
-                                        segment_time += s.d;
-
-                                        template.insert("Time", segment_time.to_string());
-                                        template.insert("Number", number.to_string());
-
-                                        stream.segments.push(Segment {
-                                            duration: s.d as f32 / timescale,
-                                            uri: base_url
-                                                .join(&template.resolve(&media))?
-                                                .to_string(),
-                                            ..Default::default()
-                                        });
-
-                                        number += 1;
-                                    }
-                                }
-
-                                segment_time += s.d;
-                            }
-                        } else if let Some(media) = &segment_template.media {
-                            // (3) SegmentTemplate@duration || (4) SegmentTemplate@index (simple addressing)
-                            let mut segment_duration = -1.0;
-                            let media = template.resolve(media);
-                            let timescale = segment_template.timescale.unwrap_or(1) as f32;
-
-                            if let Some(x) = segment_template.duration {
-                                segment_duration = x as f32 / timescale;
-                            }
-
-                            if segment_duration < 0.0 {
-                                bail!(
-                                    "Representation is missing SegmentTemplate@duration attribute."
-                                );
-                            }
-
-                            let mut number = segment_template.startNumber.unwrap_or(1) as i64;
-                            let total_number =
-                                number + (period_duration_secs / segment_duration).round() as i64;
-
-                            // // For a live manifest (dynamic MPD), we look at the time elapsed since now
-                            // // and the mpd.availabilityStartTime to determine the correct value for
-                            // // startNumber, based on duration and timescale. The latest available
-                            // // segment is numbered
-                            // //
-                            // //    LSN = floor((now - (availabilityStartTime+PST))/segmentDuration + startNumber - 1)
-
-                            // // https://dashif.org/Guidelines-TimingModel/Timing-Model.pdf
-                            // // To be more precise, any LeapSecondInformation should be added to the availabilityStartTime.
-                            // if mpd_is_dynamic(mpd) {
-                            //     if let Some(start_time) = mpd.availabilityStartTime {
-                            //         let elapsed = Utc::now()
-                            //             .signed_duration_since(start_time)
-                            //             .as_seconds_f64()
-                            //             / segment_duration;
-                            //         number = (elapsed + number as f64 - 1f64).floor() as u64;
-                            //     } else {
-                            //         return Err(DashMpdError::UnhandledMediaStream(
-                            //             "dynamic manifest is missing @availabilityStartTime"
-                            //                 .to_string(),
-                            //         ));
-                            //     }
-                            // }
-
-                            for _ in 1..=total_number {
-                                template.insert("Number", number.to_string());
-
-                                stream.segments.push(Segment {
-                                    duration: segment_duration,
-                                    uri: base_url.join(&template.resolve(&media))?.to_string(),
-                                    ..Default::default()
-                                });
-
-                                number += 1;
-                            }
-                        }
-                    } else if let Some(segment_base) = &representation.SegmentBase {
-                        // (5) SegmentBase@indexRange
-                        if let Some(initialization) = &segment_base.Initialization {
-                            let byte_range = parse_range(&initialization.range);
-
-                            if let Some(source_url) = &initialization.sourceURL {
-                                init_map = Some(Map {
-                                    range: byte_range,
-                                    uri: base_url.join(&template.resolve(source_url))?.to_string(),
-                                });
-                            } else {
-                                init_map = Some(Map {
-                                    range: byte_range,
-                                    uri: base_url.to_string(),
-                                });
-                            }
-                        }
-
-                        if let Some(index_range) = parse_range(&segment_base.indexRange) {
-                            let request = client
-                                .get(base_url.as_str())
-                                .query(query)
-                                .header(header::RANGE, &index_range);
-                            let response = request.send().await?;
-                            let bytes = response.bytes().await?;
-
-                            if let Some(init_map) = &mut init_map {
-                                init_map.range = Some(Range {
-                                    end: index_range.end,
-                                    start: 0,
-                                })
-                            }
-
-                            for range in SidxBox::from_init(&bytes, index_range.start)?
-                                .map(|x| x.ranges)
-                                .unwrap_or(Vec::with_capacity(0))
-                            {
-                                stream.segments.push(Segment {
-                                    range: Some(Range {
-                                        end: range.end,
-                                        start: range.start,
-                                    }),
-                                    uri: base_url.to_string(),
-                                    ..Default::default()
-                                });
-                            }
-                        } else {
-                            stream.segments.push(Segment {
-                                uri: base_url.to_string(),
-                                ..Default::default()
-                            });
-                        }
-                    } else if stream.segments.is_empty() && !representation.BaseURL.is_empty() {
-                        // (6) Plain BaseURL
-                        stream.segments.push(Segment {
-                            duration: period_duration_secs,
-                            uri: base_url.to_string(),
-                            ..Default::default()
-                        });
-                    }
-
-                    if stream.segments.is_empty() {
-                        bail!("no usable addressing mode identified for representation.");
-                    }
-
-                    if let Some(first_segment) = stream.segments.get_mut(0) {
-                        let mut encryption_type = KeyMethod::None;
-                        let mut default_kid = None;
-
-                        for content_protection in &representation.ContentProtection {
-                            if default_kid.is_none() && content_protection.default_KID.is_some() {
-                                default_kid = content_protection.default_KID.clone();
-                            }
-
-                            // content_protection.value = "cenc" | "cbcs" | "cens" | "cbc1"
-                            if encryption_type == KeyMethod::None
-                                && content_protection.value.is_some()
-                            {
-                                encryption_type = KeyMethod::Cenc;
-                            }
-                        }
-
-                        if encryption_type == KeyMethod::None || default_kid.is_none() {
-                            for content_protection in &adaptation_set.ContentProtection {
-                                if default_kid.is_none() && content_protection.default_KID.is_some()
-                                {
-                                    default_kid = content_protection.default_KID.clone();
-                                }
-
-                                if encryption_type == KeyMethod::None
-                                    && content_protection.value.is_some()
-                                {
-                                    encryption_type = KeyMethod::Cenc;
-                                }
-                            }
-                        }
-
-                        default_kid = default_kid.map(|x| x.to_lowercase());
-
-                        first_segment.key = match encryption_type {
-                            KeyMethod::None => None,
-                            x => Some(Key {
-                                default_kid,
-                                iv: None,
-                                key_format: None,
-                                method: x,
-                                uri: None,
-                            }),
-                        };
-
-                        first_segment.map = init_map;
-                    }
+            // content_protection.value = "cenc" | "cbcs" | "cens" | "cbc1"
+            if encryption_type == KeyMethod::None && content_protection.value.is_some() {
+                encryption_type = KeyMethod::Cenc;
+            }
+        }
+
+        if encryption_type == KeyMethod::None || default_kid.is_none() {
+            for content_protection in &adaptation_set.ContentProtection {
+                if default_kid.is_none() && content_protection.default_KID.is_some() {
+                    default_kid = content_protection.default_KID.clone();
+                }
+
+                if encryption_type == KeyMethod::None && content_protection.value.is_some() {
+                    encryption_type = KeyMethod::Cenc;
                 }
             }
         }
+
+        default_kid = default_kid.map(|x| x.to_lowercase());
+
+        first_segment.key = match encryption_type {
+            KeyMethod::None => None,
+            x => Some(Key {
+                default_kid,
+                iv: None,
+                key_format: None,
+                method: x,
+                uri: None,
+            }),
+        };
+
+        first_segment.map = init_map;
     }
 
-    stream.uri = base_url.to_owned();
-    Ok(())
+    Ok(segments)
+}
+
+/// Role and accessibility descriptors, named after `urn:mpeg:dash:role:2011` values.
+fn roles(adaptation_set: &AdaptationSet) -> Vec<String> {
+    let descriptors = adaptation_set
+        .Role
+        .iter()
+        .map(|x| (x.schemeIdUri.as_str(), x.value.as_deref()))
+        .chain(
+            adaptation_set
+                .Accessibility
+                .iter()
+                .map(|x| (x.schemeIdUri.as_str(), x.value.as_deref())),
+        );
+
+    let mut roles = Vec::new();
+
+    for descriptor in descriptors {
+        let role = match descriptor {
+            ("urn:mpeg:dash:role:2011", Some("forced_subtitle")) => "forced-subtitle",
+            ("urn:mpeg:dash:role:2011", Some(value)) => value,
+            ("urn:tva:metadata:cs:AudioPurposeCS:2007", Some("1")) => "description",
+            ("urn:tva:metadata:cs:AudioPurposeCS:2007", Some("2")) => "sdh",
+            ("urn:scte:dash:cc:cea-608:2015" | "urn:scte:dash:cc:cea-708:2015", _) => "caption",
+            _ => continue,
+        };
+
+        if !roles.iter().any(|x| x == role) {
+            roles.push(role.to_owned());
+        }
+    }
+
+    roles
+}
+
+/// Dynamic range from the `TransferCharacteristics` descriptors, representation ones take
+/// precedence over the adaptation set.
+fn video_range(
+    adaptation_set: &AdaptationSet,
+    representation: &Representation,
+) -> Option<VideoRange> {
+    representation
+        .essential_property
+        .iter()
+        .map(|x| (&x.schemeIdUri, &x.value))
+        .chain(
+            representation
+                .supplemental_property
+                .iter()
+                .map(|x| (&x.schemeIdUri, &x.value)),
+        )
+        .chain(
+            adaptation_set
+                .essential_property
+                .iter()
+                .map(|x| (&x.schemeIdUri, &x.value)),
+        )
+        .chain(
+            adaptation_set
+                .supplemental_property
+                .iter()
+                .map(|x| (&x.schemeIdUri, &x.value)),
+        )
+        .filter(|(scheme, _)| *scheme == "urn:mpeg:mpegB:cicp:TransferCharacteristics")
+        .find_map(|(_, value)| {
+            value
+                .as_deref()
+                .and_then(VideoRange::from_transfer_characteristics)
+        })
+}
+
+fn media_type(
+    adaptation_set: &AdaptationSet,
+    representation: &Representation,
+) -> (MediaType, Option<String>) {
+    let codecs = representation
+        .codecs
+        .as_ref()
+        .or(adaptation_set.codecs.as_ref());
+
+    let mime_type = representation
+        .mimeType
+        .clone()
+        .or(adaptation_set.mimeType.clone())
+        .or(representation.contentType.clone())
+        .or(adaptation_set.contentType.clone());
+
+    let mut media_type = if let Some(mime_type) = &mime_type {
+        match mime_type.as_str() {
+            "application/ttml+xml" | "application/x-sami" => MediaType::Subtitles,
+            x if x.starts_with("audio") => MediaType::Audio,
+            x if x.starts_with("text") => MediaType::Subtitles,
+            x if x.starts_with("video") => MediaType::Video,
+            _ => MediaType::Undefined,
+        }
+    } else {
+        MediaType::Undefined
+    };
+
+    if media_type == MediaType::Undefined
+        && let Some(codecs) = codecs
+    {
+        media_type = match codecs.as_str() {
+            "wvtt" | "stpp" => MediaType::Subtitles,
+            x if x.starts_with("stpp.") => MediaType::Subtitles,
+            _ => media_type,
+        };
+    }
+
+    (media_type, mime_type)
+}
+
+/// Find the representation in `period` which continues the track of `representation`.
+/// Candidates must share media type, language, codec family and either AdaptationSet@id or
+/// Representation@id, so that ad periods or other camera angles are never stitched in.
+/// Among them the same Representation@id is preferred, then the closest bandwidth.
+fn find_track(
+    period: &Period,
+    adaptation_set: &AdaptationSet,
+    representation: &Representation,
+) -> Option<(usize, usize)> {
+    let codec_family = |a: &AdaptationSet, r: &Representation| {
+        r.codecs
+            .as_ref()
+            .or(a.codecs.as_ref())
+            .and_then(|x| x.split('.').next().map(|x| x.to_owned()))
+    };
+    let media_type = media_type(adaptation_set, representation).0;
+    let codecs = codec_family(adaptation_set, representation);
+
+    period
+        .adaptations
+        .iter()
+        .enumerate()
+        .flat_map(|(a_index, a)| {
+            a.representations
+                .iter()
+                .enumerate()
+                .map(move |(r_index, r)| (a_index, a, r_index, r))
+        })
+        .filter(|(_, a, _, r)| {
+            self::media_type(a, r).0 == media_type
+                && a.lang == adaptation_set.lang
+                && codec_family(a, r) == codecs
+                && ((a.id.is_some() && a.id == adaptation_set.id)
+                    || (r.id.is_some() && r.id == representation.id))
+        })
+        .min_by_key(|(_, a, _, r)| {
+            (
+                r.id != representation.id,
+                a.id != adaptation_set.id,
+                r.bandwidth
+                    .unwrap_or(0)
+                    .abs_diff(representation.bandwidth.unwrap_or(0)),
+            )
+        })
+        .map(|(a_index, _, r_index, _)| (a_index, r_index))
+}
+
+/// Start and duration (in seconds) of a period. Missing values are derived from the
+/// previous period, the next Period@start or MPD@mediaPresentationDuration.
+fn period_timing(playlist: &MPD, period_index: usize) -> (f32, f32) {
+    let mut start = 0.0;
+    let mut duration = 0.0;
+
+    for (index, period) in playlist.periods.iter().enumerate().take(period_index + 1) {
+        start += duration;
+
+        if let Some(x) = &period.start {
+            start = x.as_secs_f32();
+        }
+
+        duration = if let Some(x) = &period.duration {
+            x.as_secs_f32()
+        } else if let Some(x) = playlist
+            .periods
+            .get(index + 1)
+            .and_then(|x| x.start.as_ref())
+        {
+            x.as_secs_f32() - start
+        } else if let Some(x) = &playlist.mediaPresentationDuration {
+            x.as_secs_f32() - start
+        } else {
+            0.0
+        };
+    }
+
+    (start, duration.max(0.0))
+}
+
+/// Find the SIDX box of an on-demand mp4 file which has no SegmentBase@indexRange,
+/// by walking its top level boxes a window at a time.
+async fn locate_sidx(
+    client: &Client,
+    url: &Url,
+    query: &Vec<(String, String)>,
+) -> Result<Option<Range>> {
+    let mut offset = 0;
+
+    for _ in 0..4 {
+        let range = Range {
+            end: offset + 16383,
+            start: offset,
+        };
+        let bytes = Request::new(client, url.clone(), query, Some(&range))?
+            .send()
+            .await?;
+
+        if bytes.is_empty() {
+            break;
+        }
+
+        match SidxBox::locate(&bytes, offset) {
+            Ok(SidxLocation::Found { end, start }) => return Ok(Some(Range { end, start })),
+            Ok(SidxLocation::Continue(next)) if next > offset => offset = next,
+            _ => break,
+        }
+    }
+
+    Ok(None)
+}
+
+/// Parse the SIDX box at `index_range`, following references to nested SIDX boxes.
+async fn sidx_ranges(
+    client: &Client,
+    url: &Url,
+    query: &Vec<(String, String)>,
+    index_range: Range,
+) -> Result<Vec<SidxRange>> {
+    let mut ranges = read_sidx(client, url, query, &index_range).await?;
+
+    while let Some(index) = ranges.iter().position(|x| x.is_sidx) {
+        let range = ranges.remove(index);
+        // The reference also spans the media indexed by the nested box, which isn't needed here.
+        let range = Range {
+            end: range.end.min(range.start + 4095),
+            start: range.start,
+        };
+        ranges.splice(index..index, read_sidx(client, url, query, &range).await?);
+    }
+
+    Ok(ranges)
+}
+
+async fn read_sidx(
+    client: &Client,
+    url: &Url,
+    query: &Vec<(String, String)>,
+    range: &Range,
+) -> Result<Vec<SidxRange>> {
+    let mut bytes = Request::new(client, url.clone(), query, Some(range))?
+        .send()
+        .await?;
+    let size = bytes
+        .get(..4)
+        .map(|x| u32::from_be_bytes(x.try_into().unwrap()) as u64)
+        .unwrap_or(0);
+
+    if size > bytes.len() as u64 {
+        let range = Range {
+            end: range.start + size - 1,
+            start: range.start,
+        };
+        bytes = Request::new(client, url.clone(), query, Some(&range))?
+            .send()
+            .await?;
+    }
+
+    Ok(SidxBox::from_init(&bytes, range.start)?
+        .map(|x| x.ranges)
+        .unwrap_or_default())
+}
+
+/// Expand a SegmentTimeline into `(time, duration)` pairs in timescale units. A negative
+/// S@r repeats until the next S@t, or the period end for the last S element.
+fn expand_timeline(timeline: &SegmentTimeline, period_end: u64) -> Vec<(u64, u64)> {
+    let mut segments = vec![];
+    let mut time = 0;
+
+    for (index, s) in timeline.segments.iter().enumerate() {
+        if let Some(t) = s.t {
+            time = t;
+        }
+
+        if s.d == 0 {
+            continue;
+        }
+
+        let repeat = match s.r.unwrap_or(0) {
+            r if r >= 0 => r as u64,
+            _ => timeline
+                .segments
+                .get(index + 1)
+                .and_then(|x| x.t)
+                .unwrap_or(period_end)
+                .saturating_sub(time)
+                .div_ceil(s.d)
+                .saturating_sub(1),
+        };
+
+        for _ in 0..=repeat {
+            segments.push((time, s.d));
+            time += s.d;
+        }
+    }
+
+    segments
 }
 
 fn parse_frame_rate(frame_rate: &Option<String>) -> Option<f32> {
@@ -565,3 +919,131 @@ fn parse_range(range: &Option<String>) -> Option<Range> {
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE_URL: &str = "https://example.com/dash/manifest.mpd";
+
+    /// Segments of the first stream of a static presentation with a single representation.
+    async fn segments(template: &str) -> Vec<Segment> {
+        let mpd = dash_mpd::parse(&format!(
+            r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT10S">
+                <Period>
+                    <AdaptationSet mimeType="video/mp4">
+                        <Representation id="v1" bandwidth="1000">{template}</Representation>
+                    </AdaptationSet>
+                </Period>
+            </MPD>"#
+        ))
+        .unwrap();
+        let mut stream = parse_as_master(&mpd, BASE_URL).streams.remove(0);
+        push_segments(&mpd, &mut stream, &Client::new(), BASE_URL, &Vec::new())
+            .await
+            .unwrap();
+        stream.segments
+    }
+
+    fn uris(segments: &[Segment]) -> Vec<&str> {
+        segments
+            .iter()
+            .map(|x| x.uri.trim_start_matches("https://example.com/dash/"))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn template_duration_uneven() {
+        let segments = segments(
+            r#"<SegmentTemplate media="$RepresentationID$-$Number$.m4s" duration="4000" timescale="1000" startNumber="0"/>"#,
+        )
+        .await;
+
+        // 10s / 4s leaves a shorter last segment, which still has to be downloaded.
+        assert_eq!(uris(&segments), ["v1-0.m4s", "v1-1.m4s", "v1-2.m4s"]);
+        assert!(segments.iter().all(|x| x.duration == 4.0));
+    }
+
+    fn timeline(segments: &[Segment]) -> Vec<(f64, f32)> {
+        segments
+            .iter()
+            .map(|x| (x.start.unwrap(), x.duration))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn timeline_repeat_until_next_time() {
+        let segments = segments(
+            r#"<SegmentTemplate media="$Time$.m4s" timescale="1"><SegmentTimeline>
+                <S t="0" d="2" r="-1"/><S t="6" d="3"/>
+            </SegmentTimeline></SegmentTemplate>"#,
+        )
+        .await;
+
+        assert_eq!(uris(&segments), ["0.m4s", "2.m4s", "4.m4s", "6.m4s"]);
+        assert_eq!(
+            timeline(&segments),
+            [(0.0, 2.0), (2.0, 2.0), (4.0, 2.0), (6.0, 3.0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn timeline_repeat_until_period_end() {
+        let segments = segments(
+            r#"<SegmentTemplate media="$Number$.m4s" timescale="1"><SegmentTimeline>
+                <S t="0" d="4" r="-1"/>
+            </SegmentTimeline></SegmentTemplate>"#,
+        )
+        .await;
+
+        // The last segment may end after the period, it is still part of it.
+        assert_eq!(uris(&segments), ["1.m4s", "2.m4s", "3.m4s"]);
+        assert_eq!(timeline(&segments), [(0.0, 4.0), (4.0, 4.0), (8.0, 4.0)]);
+    }
+
+    #[tokio::test]
+    async fn timeline_presentation_time_offset() {
+        let segments = segments(
+            r#"<SegmentTemplate media="$Time%06d$.m4s" timescale="10" presentationTimeOffset="100">
+                <SegmentTimeline><S t="100" d="25" r="-1"/></SegmentTimeline>
+            </SegmentTemplate>"#,
+        )
+        .await;
+
+        // The period end is offset too, so the timeline still covers 10 seconds.
+        assert_eq!(
+            uris(&segments),
+            ["000100.m4s", "000125.m4s", "000150.m4s", "000175.m4s"]
+        );
+        assert_eq!(
+            timeline(&segments),
+            [(0.0, 2.5), (2.5, 2.5), (5.0, 2.5), (7.5, 2.5)]
+        );
+    }
+
+    #[tokio::test]
+    async fn timeline_explicit_times() {
+        let segments = segments(
+            r#"<SegmentTemplate media="$RepresentationID$/$Time$-$Number$.m4s" timescale="1" startNumber="5">
+                <SegmentTimeline><S t="0" d="2"/><S t="5" d="2" r="1"/><S t="8" d="2"/><S d="1"/></SegmentTimeline>
+            </SegmentTemplate>"#,
+        )
+        .await;
+
+        // A gap after the first segment and an overlap of the third and fourth ones.
+        assert_eq!(
+            uris(&segments),
+            [
+                "v1/0-5.m4s",
+                "v1/5-6.m4s",
+                "v1/7-7.m4s",
+                "v1/8-8.m4s",
+                "v1/10-9.m4s"
+            ]
+        );
+        assert_eq!(
+            timeline(&segments),
+            [(0.0, 2.0), (5.0, 2.0), (7.0, 2.0), (8.0, 2.0), (10.0, 1.0)]
+        );
+    }
+}
@@ -0,0 +1,605 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://www.matroska.org/technical/elements.html
+    2. https://www.webmproject.org/docs/container
+    3. https://github.com/shaka-project/shaka-player/blob/62c8367438d36c08db6440ba32f54223e0367f00/lib/media/webm_segment_index_parser.js
+
+*/
+
+use crate::{
+    playlist::{Range, Segment},
+    request::Request,
+};
+use anyhow::{Result, bail};
+use reqwest::{Client, Url};
+
+const EBML: u64 = 0x1A45DFA3;
+const SEGMENT: u64 = 0x18538067;
+const SEEK_HEAD: u64 = 0x114D9B74;
+const SEEK: u64 = 0x4DBB;
+const SEEK_ID: u64 = 0x53AB;
+const SEEK_POSITION: u64 = 0x53AC;
+const INFO: u64 = 0x1549A966;
+const TIMECODE_SCALE: u64 = 0x2AD7B1;
+const DURATION: u64 = 0x4489;
+const CLUSTER: u64 = 0x1F43B675;
+const CUES: u64 = 0x1C53BB6B;
+const CUE_POINT: u64 = 0xBB;
+const CUE_TIME: u64 = 0xB3;
+const CUE_TRACK_POSITIONS: u64 = 0xB7;
+const CUE_CLUSTER_POSITION: u64 = 0xF1;
+
+/// Bytes requested from the start of a file to find the init segment and cues when their ranges
+/// aren't given. Headers before the first cluster are small, except for codec private data.
+const HEAD_SIZE: u64 = 64 * 1024;
+
+/// Build segments from the `Cues` element of a webm file, one per indexed `Cluster`.
+/// Times and durations are in seconds, relative to the start of the file.
+///
+/// Missing ranges are located from the file, the init segment ends at the first cluster and
+/// the cues are found through the `SeekHead`. Returns the init range along with the segments,
+/// or `None` when the file has no cues.
+pub(super) async fn cue_segments(
+    client: &Client,
+    url: &Url,
+    query: &Vec<(String, String)>,
+    init_range: Option<&Range>,
+    index_range: Option<&Range>,
+) -> Result<Option<(Range, Vec<Segment>)>> {
+    let head = Range {
+        end: HEAD_SIZE - 1,
+        start: 0,
+    };
+    let init = Request::new(
+        client,
+        url.clone(),
+        query,
+        Some(init_range.unwrap_or(&head)),
+    )?
+    .send()
+    .await?;
+    let info = parse_init(&init)?;
+
+    let init_range = match (init_range, info.first_cluster) {
+        (Some(init_range), _) => init_range.to_owned(),
+        (None, Some(first_cluster)) => Range {
+            end: first_cluster - 1,
+            start: 0,
+        },
+        (None, None) => bail!("Unable to locate the first webm cluster."),
+    };
+
+    let index_range = match (index_range, info.cues) {
+        (Some(index_range), _) => index_range.to_owned(),
+        (None, Some(position)) => element_range(client, url, query, position).await?,
+        (None, None) => return Ok(None),
+    };
+
+    let index = Request::new(client, url.clone(), query, Some(&index_range))?
+        .send()
+        .await?;
+    let cues = parse_cues(&index)?;
+
+    let Some((_, last_position)) = cues.last() else {
+        bail!("WebM Cues element has no cue points.");
+    };
+
+    // The last cluster runs until the Cues element (placed after clusters) or the end of the file.
+    let last_position = info.segment_offset + last_position;
+    let end = if index_range.start > last_position {
+        index_range.start
+    } else {
+        Request::new(client, url.clone(), query, None)?
+            .content_length(client)
+            .await?
+    };
+
+    Ok(Some((
+        init_range,
+        segments(&info, &cues, end, url.as_str())?,
+    )))
+}
+
+/// Byte range of the element at `position`, read from its header.
+async fn element_range(
+    client: &Client,
+    url: &Url,
+    query: &Vec<(String, String)>,
+    position: u64,
+) -> Result<Range> {
+    let range = Range {
+        end: position + 11,
+        start: position,
+    };
+    let header = Request::new(client, url.clone(), query, Some(&range))?
+        .send()
+        .await?;
+    let mut reader = Reader::new(&header);
+    reader.vint(false)?;
+    let size = reader.vint(true)?;
+
+    if size == u64::MAX {
+        bail!("WebM element at byte {} has an unknown size.", position);
+    }
+
+    Ok(Range {
+        end: position + reader.position as u64 + size - 1,
+        start: position,
+    })
+}
+
+/// One segment per cluster, `cues` must be sorted by position. `end` is the exclusive end of
+/// the last cluster.
+fn segments(info: &Info, cues: &[(u64, u64)], end: u64, uri: &str) -> Result<Vec<Segment>> {
+    let scale = info.timecode_scale as f64 / 1_000_000_000.0;
+    let mut segments = Vec::with_capacity(cues.len());
+
+    for (i, (time, position)) in cues.iter().enumerate() {
+        let start = info.segment_offset + position;
+        let (next_time, next_position) = match cues.get(i + 1) {
+            Some((time, position)) => (Some(*time as f64), info.segment_offset + position),
+            None => (info.duration, end),
+        };
+
+        if next_position <= start {
+            bail!(
+                "Unable to determine the size of webm cluster at byte {}.",
+                start
+            );
+        }
+
+        segments.push(Segment {
+            duration: next_time
+                .map(|x| ((x - *time as f64) * scale) as f32)
+                .unwrap_or(0.0)
+                .max(0.0),
+            range: Some(Range {
+                end: next_position - 1,
+                start,
+            }),
+            start: Some(*time as f64 * scale),
+            uri: uri.to_owned(),
+            ..Default::default()
+        });
+    }
+
+    Ok(segments)
+}
+
+struct Info {
+    /// Absolute offset of the `Cues` element, from the `SeekHead` or found before the clusters.
+    cues: Option<u64>,
+    /// Duration in timecode scale units.
+    duration: Option<f64>,
+    /// Absolute offset of the first `Cluster`, where the init segment ends.
+    first_cluster: Option<u64>,
+    /// Absolute offset of the `Segment` payload, which cluster positions are relative to.
+    segment_offset: u64,
+    /// Nanoseconds per timecode unit.
+    timecode_scale: u64,
+}
+
+/// Parse the elements before the first cluster. `data` is the init segment, or the start of
+/// the file which may end in the middle of an element.
+fn parse_init(data: &[u8]) -> Result<Info> {
+    let mut reader = Reader::new(data);
+
+    let (id, size) = reader.element()?;
+
+    if id != EBML {
+        bail!("WebM init segment doesn't start with an EBML header.");
+    }
+
+    reader.skip(size)?;
+
+    let (id, _) = reader.element()?;
+
+    if id != SEGMENT {
+        bail!("WebM init segment is missing the Segment element.");
+    }
+
+    let segment_offset = reader.position as u64;
+    let mut info = Info {
+        cues: None,
+        duration: None,
+        first_cluster: None,
+        segment_offset,
+        timecode_scale: 1_000_000,
+    };
+
+    while reader.has_more_data() {
+        let position = reader.position as u64;
+        let (id, size) = reader.element()?;
+
+        match id {
+            CLUSTER => {
+                info.first_cluster = Some(position);
+                break;
+            }
+            CUES => info.cues = Some(position),
+            _ => (),
+        }
+
+        let mut child = Reader::new(reader.read(size)?);
+
+        match id {
+            INFO => {
+                while child.has_more_data() {
+                    let (id, size) = child.element()?;
+                    let value = child.read(size)?;
+
+                    match id {
+                        TIMECODE_SCALE => info.timecode_scale = read_uint(value),
+                        DURATION => info.duration = read_float(value),
+                        _ => (),
+                    }
+                }
+            }
+            SEEK_HEAD if info.cues.is_none() => {
+                while child.has_more_data() {
+                    let (id, size) = child.element()?;
+                    let mut seek = Reader::new(child.read(size)?);
+
+                    if id != SEEK {
+                        continue;
+                    }
+
+                    let mut seek_id = None;
+                    let mut seek_position = None;
+
+                    while seek.has_more_data() {
+                        let (id, size) = seek.element()?;
+                        let value = seek.read(size)?;
+
+                        match id {
+                            SEEK_ID => seek_id = Some(read_uint(value)),
+                            SEEK_POSITION => seek_position = Some(read_uint(value)),
+                            _ => (),
+                        }
+                    }
+
+                    if seek_id == Some(CUES) {
+                        info.cues = seek_position.map(|x| segment_offset + x);
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Ok(info)
+}
+
+/// Returns `(CueTime, CueClusterPosition)` pairs sorted by position. Cue points of the same
+/// cluster are merged, keeping the earliest time.
+fn parse_cues(data: &[u8]) -> Result<Vec<(u64, u64)>> {
+    let mut reader = Reader::new(data);
+    let (id, size) = reader.element()?;
+
+    if id != CUES {
+        bail!("WebM index range doesn't point to a Cues element.");
+    }
+
+    let mut reader = Reader::new(reader.read(size)?);
+    let mut cues = Vec::new();
+
+    while reader.has_more_data() {
+        let (id, size) = reader.element()?;
+        let data = reader.read(size)?;
+
+        if id != CUE_POINT {
+            continue;
+        }
+
+        let mut cue_point = Reader::new(data);
+        let mut time = None;
+        let mut position = None;
+
+        while cue_point.has_more_data() {
+            let (id, size) = cue_point.element()?;
+            let value = cue_point.read(size)?;
+
+            match id {
+                CUE_TIME => time = Some(read_uint(value)),
+                CUE_TRACK_POSITIONS if position.is_none() => {
+                    let mut track_positions = Reader::new(value);
+
+                    while track_positions.has_more_data() {
+                        let (id, size) = track_positions.element()?;
+                        let value = track_positions.read(size)?;
+
+                        if id == CUE_CLUSTER_POSITION {
+                            position = Some(read_uint(value));
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if let (Some(time), Some(position)) = (time, position) {
+            cues.push((time, position));
+        }
+    }
+
+    cues.sort_by_key(|&(time, position)| (position, time));
+    cues.dedup_by_key(|x| x.1);
+    Ok(cues)
+}
+
+/// Minimal EBML reader.
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn has_more_data(&self) -> bool {
+        self.position < self.data.len()
+    }
+
+    /// Read an element id and its data size. Unknown sizes span the remaining data.
+    fn element(&mut self) -> Result<(u64, u64)> {
+        let id = self.vint(false)?;
+        let size = self.vint(true)?;
+        let remaining = (self.data.len() - self.position) as u64;
+        Ok((id, size.min(remaining)))
+    }
+
+    fn read(&mut self, size: u64) -> Result<&'a [u8]> {
+        let end = self.position + size as usize;
+
+        if end > self.data.len() {
+            bail!("Unexpected end of EBML data.");
+        }
+
+        let data = &self.data[self.position..end];
+        self.position = end;
+        Ok(data)
+    }
+
+    fn skip(&mut self, size: u64) -> Result<()> {
+        self.read(size)?;
+        Ok(())
+    }
+
+    /// Read a variable size integer. Ids keep their length marker, sizes don't.
+    fn vint(&mut self, strip_marker: bool) -> Result<u64> {
+        let Some(&first) = self.data.get(self.position) else {
+            bail!("Unexpected end of EBML data.");
+        };
+
+        let length = first.leading_zeros() as usize + 1;
+
+        if length > 8 {
+            bail!("Invalid EBML variable size integer.");
+        }
+
+        let bytes = self.read(length as u64)?;
+        let mut value = bytes.iter().fold(0, |acc, x| (acc << 8) | *x as u64);
+
+        if strip_marker {
+            value &= (1 << (7 * length)) - 1;
+
+            // All data bits set means the size is unknown.
+            if value == (1 << (7 * length)) - 1 {
+                return Ok(u64::MAX);
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+fn read_uint(data: &[u8]) -> u64 {
+    data.iter().fold(0, |acc, x| (acc << 8) | *x as u64)
+}
+
+fn read_float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes(data.try_into().unwrap()) as f64),
+        8 => Some(f64::from_be_bytes(data.try_into().unwrap())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    fn element(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut element = id.to_vec();
+        element.push(0x80 | data.len() as u8);
+        element.extend_from_slice(data);
+        element
+    }
+
+    fn cue_point(time: u8, position: u8) -> Vec<u8> {
+        let positions = [element(&[0xF7], &[1]), element(&[0xF1], &[position])].concat();
+        element(
+            &[0xBB],
+            &[element(&[0xB3], &[time]), element(&[0xB7], &positions)].concat(),
+        )
+    }
+
+    fn init() -> Vec<u8> {
+        let info = [
+            element(&[0x2A, 0xD7, 0xB1], &[0x0F, 0x42, 0x40]),
+            element(&[0x44, 0x89], &6000.0_f32.to_be_bytes()),
+        ]
+        .concat();
+
+        [
+            element(&[0x1A, 0x45, 0xDF, 0xA3], &[0x42, 0x86, 0x81, 0x01]),
+            // Unknown size, the Segment spans the whole file.
+            vec![0x18, 0x53, 0x80, 0x67, 0xFF],
+            element(&[0x11, 0x4D, 0x9B, 0x74], &[]),
+            element(&[0x15, 0x49, 0xA9, 0x66], &info),
+        ]
+        .concat()
+    }
+
+    fn cluster() -> Vec<u8> {
+        element(&[0x1F, 0x43, 0xB6, 0x75], &[0; 10])
+    }
+
+    fn cues(points: &[(u8, u8)]) -> Vec<u8> {
+        let points = points
+            .iter()
+            .map(|&(time, position)| cue_point(time, position))
+            .collect::<Vec<_>>()
+            .concat();
+        element(&[0x1C, 0x53, 0xBB, 0x6B], &points)
+    }
+
+    /// A file with two clusters followed by the cues, which are listed in the `SeekHead`.
+    fn file() -> Vec<u8> {
+        let init = init();
+        let seek = [
+            element(&[0x53, 0xAB], &[0x1C, 0x53, 0xBB, 0x6B]),
+            element(&[0x53, 0xAC], &[68]),
+        ]
+        .concat();
+
+        [
+            &init[..14],
+            &element(&[0x11, 0x4D, 0x9B, 0x74], &element(&[0x4D, 0xBB], &seek)),
+            &init[19..],
+            &cluster(),
+            &cluster(),
+            &cues(&[(0, 38), (200, 53)]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn init_info() {
+        let info = parse_init(&init()).unwrap();
+        assert_eq!(info.segment_offset, 14);
+        assert_eq!(info.timecode_scale, 1_000_000);
+        assert_eq!(info.duration, Some(6000.0));
+    }
+
+    #[test]
+    fn init_without_ebml_header() {
+        assert!(parse_init(&init()[9..]).is_err());
+    }
+
+    #[test]
+    fn unsorted_cues() {
+        let cues = [
+            cue_point(40, 90),
+            cue_point(0, 30),
+            cue_point(20, 60),
+            cue_point(10, 30),
+            cue_point(30, 60),
+        ]
+        .concat();
+        let cues = element(&[0x1C, 0x53, 0xBB, 0x6B], &cues);
+
+        assert_eq!(parse_cues(&cues).unwrap(), [(0, 30), (20, 60), (40, 90)]);
+    }
+
+    #[test]
+    fn cluster_segments() {
+        let info = parse_init(&init()).unwrap();
+        let segments = segments(&info, &[(0, 30), (2000, 60), (4000, 90)], 200, "a.webm").unwrap();
+
+        assert_eq!(
+            segments
+                .iter()
+                .map(|x| {
+                    let range = x.range.as_ref().unwrap();
+                    (range.start, range.end, x.start.unwrap(), x.duration)
+                })
+                .collect::<Vec<_>>(),
+            [
+                (44, 73, 0.0, 2.0),
+                (74, 103, 2.0, 2.0),
+                (104, 199, 4.0, 2.0)
+            ]
+        );
+    }
+
+    #[test]
+    fn last_cluster_without_end() {
+        let info = parse_init(&init()).unwrap();
+        assert!(segments(&info, &[(0, 30)], 0, "a.webm").is_err());
+    }
+
+    #[test]
+    fn unknown_vint_size() {
+        let mut reader = Reader::new(&[0x1F, 0x43, 0xB6, 0x75, 0x81, 0xFF]);
+        assert_eq!(reader.vint(false).unwrap(), 0x1F43B675);
+        assert_eq!(reader.vint(true).unwrap(), 1);
+        assert_eq!(reader.vint(true).unwrap(), u64::MAX);
+        assert!(Reader::new(&[0x00]).vint(true).is_err());
+    }
+
+    #[test]
+    fn seek_head_cues() {
+        let info = parse_init(&file()).unwrap();
+        assert_eq!(info.cues, Some(82));
+        assert_eq!(info.first_cluster, Some(52));
+    }
+
+    #[test]
+    fn cues_before_clusters() {
+        let data = [init(), cues(&[(0, 0)]), cluster()].concat();
+        let info = parse_init(&data).unwrap();
+        assert_eq!(info.cues, Some(38));
+        assert_eq!(info.first_cluster, Some(38 + cues(&[(0, 0)]).len() as u64));
+    }
+
+    #[tokio::test]
+    async fn segments_without_ranges() {
+        let url = format!(
+            "data:video/webm;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(file())
+        )
+        .parse::<Url>()
+        .unwrap();
+
+        let (init_range, segments) = cue_segments(&Client::new(), &url, &Vec::new(), None, None)
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert_eq!((init_range.start, init_range.end), (0, 51));
+        assert_eq!(
+            segments
+                .iter()
+                .map(|x| {
+                    let range = x.range.as_ref().unwrap();
+                    (range.start, range.end)
+                })
+                .collect::<Vec<_>>(),
+            [(52, 66), (67, 81)]
+        );
+    }
+
+    #[tokio::test]
+    async fn segments_without_cues() {
+        let data = [init(), cluster()].concat();
+        let url = format!(
+            "data:video/webm;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(data)
+        )
+        .parse::<Url>()
+        .unwrap();
+
+        assert!(
+            cue_segments(&Client::new(), &url, &Vec::new(), None, None)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,299 @@
+/*
+    REFERENCES
+    ----------
+
+    1. ISO/IEC 23009-1 5.5 Remote elements
+    2. https://www.w3.org/TR/xlink11
+
+*/
+
+use crate::request::Cache;
+use anyhow::{Result, anyhow, bail};
+use dash_mpd::{BaseURL, MPD};
+use reqwest::{Client, Url};
+
+const RESOLVE_TO_ZERO: &str = "urn:mpeg:dash:resolve-to-zero:2013";
+const WRAPPER: &str =
+    r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" xmlns:xlink="http://www.w3.org/1999/xlink">"#;
+
+/// Remote elements may link to further remote elements, up to this many levels.
+const MAX_DEPTH: usize = 5;
+
+/// Replace Period and AdaptationSet elements having an `xlink:href` with `xlink:actuate="onLoad"`
+/// by the remote elements. A `urn:mpeg:dash:resolve-to-zero:2013` link removes the element.
+/// Links inside remote elements are resolved as well, up to [`MAX_DEPTH`] levels.
+pub(crate) async fn resolve_xlinks(
+    mpd: &mut MPD,
+    base_url: &Url,
+    cache: &Cache,
+    client: &Client,
+    query: &Vec<(String, String)>,
+) -> Result<()> {
+    for depth in 0.. {
+        if !mpd
+            .periods
+            .iter()
+            .any(|x| remote_href(&x.href, &x.actuate).is_some())
+        {
+            break;
+        }
+
+        if depth == MAX_DEPTH {
+            bail!("Remote periods are nested more than {MAX_DEPTH} levels deep.");
+        }
+
+        let mut periods = Vec::with_capacity(mpd.periods.len());
+
+        for period in std::mem::take(&mut mpd.periods) {
+            let Some(href) = remote_href(&period.href, &period.actuate) else {
+                periods.push(period);
+                continue;
+            };
+
+            if href == RESOLVE_TO_ZERO {
+                continue;
+            }
+
+            let url = base_url.join(href)?;
+            let xml = fetch(&url, cache, client, query).await?;
+
+            for mut period in parse(&url, &format!("{WRAPPER}{xml}</MPD>"))?.periods {
+                // Relative urls inside remote elements resolve against the remote document.
+                rebase(&mut period.BaseURL, &url)?;
+                rebase_href(&mut period.href, &url)?;
+
+                for adaptation_set in &mut period.adaptations {
+                    rebase_href(&mut adaptation_set.href, &url)?;
+                }
+
+                periods.push(period);
+            }
+        }
+
+        mpd.periods = periods;
+    }
+
+    for period in &mut mpd.periods {
+        for depth in 0.. {
+            if !period
+                .adaptations
+                .iter()
+                .any(|x| remote_href(&x.href, &x.actuate).is_some())
+            {
+                break;
+            }
+
+            if depth == MAX_DEPTH {
+                bail!("Remote adaptation sets are nested more than {MAX_DEPTH} levels deep.");
+            }
+
+            let mut adaptation_sets = Vec::with_capacity(period.adaptations.len());
+
+            for adaptation_set in std::mem::take(&mut period.adaptations) {
+                let Some(href) = remote_href(&adaptation_set.href, &adaptation_set.actuate) else {
+                    adaptation_sets.push(adaptation_set);
+                    continue;
+                };
+
+                if href == RESOLVE_TO_ZERO {
+                    continue;
+                }
+
+                let url = base_url.join(href)?;
+                let xml = fetch(&url, cache, client, query).await?;
+
+                for remote in
+                    parse(&url, &format!("{WRAPPER}<Period>{xml}</Period></MPD>"))?.periods
+                {
+                    for mut adaptation_set in remote.adaptations {
+                        rebase(&mut adaptation_set.BaseURL, &url)?;
+                        rebase_href(&mut adaptation_set.href, &url)?;
+                        adaptation_sets.push(adaptation_set);
+                    }
+                }
+            }
+
+            period.adaptations = adaptation_sets;
+        }
+    }
+
+    Ok(())
+}
+
+fn remote_href<'a>(href: &'a Option<String>, actuate: &Option<String>) -> Option<&'a str> {
+    let href = href.as_deref()?;
+
+    if href == RESOLVE_TO_ZERO || actuate.as_deref() == Some("onLoad") {
+        Some(href)
+    } else {
+        None
+    }
+}
+
+async fn fetch(
+    url: &Url,
+    cache: &Cache,
+    client: &Client,
+    query: &Vec<(String, String)>,
+) -> Result<String> {
+    let data = cache.fetch(client, url.clone(), query, None).await?;
+    let xml = String::from_utf8_lossy(&data);
+    let xml = xml.trim_start();
+
+    // Drop the xml declaration, remote elements are wrapped inside a dummy MPD.
+    let xml = xml
+        .strip_prefix("<?xml")
+        .and_then(|x| x.split_once("?>"))
+        .map_or(xml, |x| x.1);
+    Ok(xml.to_owned())
+}
+
+fn parse(url: &Url, xml: &str) -> Result<MPD> {
+    dash_mpd::parse(xml).map_err(|e| anyhow!("Failed to parse remote element {}: {}", url, e))
+}
+
+fn rebase(base_urls: &mut Vec<BaseURL>, url: &Url) -> Result<()> {
+    if base_urls.is_empty() {
+        base_urls.push(BaseURL {
+            base: url.to_string(),
+            ..Default::default()
+        });
+    }
+
+    for base_url in base_urls {
+        base_url.base = url.join(&base_url.base)?.to_string();
+    }
+
+    Ok(())
+}
+
+fn rebase_href(href: &mut Option<String>, url: &Url) -> Result<()> {
+    if let Some(href) = href
+        && href != RESOLVE_TO_ZERO
+    {
+        *href = url.join(href)?.to_string();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+
+    /// Remote elements are served from memory as `data:` uris.
+    fn remote(xml: &str) -> String {
+        format!(
+            "data:application/xml;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(xml)
+        )
+    }
+
+    async fn resolve(periods: &str) -> Result<MPD> {
+        let mut mpd = dash_mpd::parse(&format!("{WRAPPER}{periods}</MPD>"))?;
+        resolve_xlinks(
+            &mut mpd,
+            &"https://example.com/manifest.mpd".parse()?,
+            &Cache::default(),
+            &Client::new(),
+            &Vec::new(),
+        )
+        .await?;
+        Ok(mpd)
+    }
+
+    fn period_ids(mpd: &MPD) -> Vec<&str> {
+        mpd.periods
+            .iter()
+            .map(|x| x.id.as_deref().unwrap_or_default())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn periods_on_load() {
+        let href = remote(
+            r#"<?xml version="1.0"?><Period id="p2"/><Period id="p3"><AdaptationSet id="1"/></Period>"#,
+        );
+        let mpd = resolve(&format!(
+            r#"<Period id="p1"/><Period xlink:href="{href}" xlink:actuate="onLoad"/><Period id="p4"/>"#
+        ))
+        .await
+        .unwrap();
+
+        assert_eq!(period_ids(&mpd), ["p1", "p2", "p3", "p4"]);
+        assert_eq!(mpd.periods[2].adaptations.len(), 1);
+        assert!(mpd.periods.iter().all(|x| x.href.is_none()));
+    }
+
+    #[tokio::test]
+    async fn adaptation_sets_on_load() {
+        let href = remote(
+            r#"<AdaptationSet id="2" mimeType="video/mp4"/><AdaptationSet id="3" mimeType="audio/mp4"/>"#,
+        );
+        let mpd = resolve(&format!(
+            r#"<Period id="p1"><AdaptationSet id="1"/><AdaptationSet xlink:href="{href}" xlink:actuate="onLoad"/></Period>"#
+        ))
+        .await
+        .unwrap();
+
+        let adaptation_sets = &mpd.periods[0].adaptations;
+        assert_eq!(
+            adaptation_sets
+                .iter()
+                .map(|x| x.id.as_deref().unwrap_or_default())
+                .collect::<Vec<_>>(),
+            ["1", "2", "3"]
+        );
+        assert_eq!(adaptation_sets[2].mimeType.as_deref(), Some("audio/mp4"));
+    }
+
+    #[tokio::test]
+    async fn resolve_to_zero() {
+        let mpd = resolve(&format!(
+            r#"<Period id="p1"><AdaptationSet id="1" xlink:href="{RESOLVE_TO_ZERO}"/><AdaptationSet id="2"/></Period>
+            <Period id="p2" xlink:href="{RESOLVE_TO_ZERO}" xlink:actuate="onLoad"/>"#
+        ))
+        .await
+        .unwrap();
+
+        assert_eq!(period_ids(&mpd), ["p1"]);
+        assert_eq!(mpd.periods[0].adaptations.len(), 1);
+        assert_eq!(mpd.periods[0].adaptations[0].id.as_deref(), Some("2"));
+    }
+
+    #[tokio::test]
+    async fn on_request_is_kept() {
+        let href = remote(r#"<Period id="remote"/>"#);
+        let mpd = resolve(&format!(
+            r#"<Period id="p1" xlink:href="{href}" xlink:actuate="onRequest"/><Period id="p2" xlink:href="{href}"/>"#
+        ))
+        .await
+        .unwrap();
+
+        // onRequest is the default and only resolved when the element is needed.
+        assert_eq!(period_ids(&mpd), ["p1", "p2"]);
+        assert_eq!(mpd.periods[0].href.as_deref(), Some(href.as_str()));
+    }
+
+    #[tokio::test]
+    async fn nested_depth_limit() {
+        let mut href = remote(r#"<Period id="last"/>"#);
+
+        for _ in 0..MAX_DEPTH {
+            href = remote(&format!(
+                r#"<Period xlink:href="{href}" xlink:actuate="onLoad"/>"#
+            ));
+        }
+
+        let periods = format!(r#"<Period xlink:href="{href}" xlink:actuate="onLoad"/>"#);
+        assert!(resolve(&periods).await.is_err());
+
+        let href = remote(&format!(
+            r#"<Period xlink:href="{}" xlink:actuate="onLoad"/>"#,
+            remote(r#"<Period id="last"/>"#)
+        ));
+        let periods = format!(r#"<Period xlink:href="{href}" xlink:actuate="onLoad"/>"#);
+        assert_eq!(period_ids(&resolve(&periods).await.unwrap()), ["last"]);
+    }
+}
@@ -1,4 +1,7 @@
-use crate::playlist::{KeyMethod, MediaPlaylist, Segment};
+use crate::{
+    playlist::{KeyMethod, MediaPlaylist, Segment},
+    request::Cache,
+};
 use anyhow::{Result, bail};
 use colored::Colorize;
 use log::info;
@@ -85,6 +88,7 @@ pub fn check_unsupported_enc(streams: &Vec<MediaPlaylist>) -> Result<()> {
 
 pub async fn get_default_kids(
     streams: &[MediaPlaylist],
+    cache: &Cache,
     client: &Client,
     query: &Vec<(String, String)>,
 ) -> Result<HashSet<String>> {
@@ -98,10 +102,13 @@ pub async fn get_default_kids(
 
     let mut pssh_hash = HashSet::new();
 
+    let mut init_segs = Vec::new();
+
     for stream in streams {
-        let Some(init_seg) = stream.fetch_init_seg(client, query).await? else {
-            continue;
-        };
+        init_segs.extend(stream.fetch_init_segs(cache, client, query).await?);
+    }
+
+    for init_seg in init_segs {
         let pssh = PsshBox::from_init(&init_seg)?;
 
         for data in pssh.data {
@@ -1,14 +1,15 @@
 use crate::{
-    options::{Interaction, SelectOptions},
+    options::{Interaction, MuxOptions, SelectOptions},
     playlist::{MasterPlaylist, MediaPlaylist, PlaylistType},
+    request::Cache,
     utils,
 };
 use anyhow::{Result, anyhow, bail};
-use base64::Engine;
 use colored::Colorize;
+use dash_mpd::MPD;
 use log::info;
 use reqwest::{Client, Url, header};
-use std::path::Path;
+use std::{collections::HashMap, path::Path};
 use tokio::fs;
 
 pub struct FetchedPlaylist {
@@ -28,22 +29,15 @@ impl FetchedPlaylist {
         let mut typ = None;
 
         if path.exists() {
-            if base_url.is_none() {
-                bail!("--baseurl flag is required for local playlist file.");
-            }
-
-            match path.extension() {
-                Some(ext) if ext == "m3u" || ext == "m3u8" => typ = Some(PlaylistType::Hls),
-                Some(ext) if ext == "mpd" => typ = Some(PlaylistType::Dash),
-                _ => (),
+            Self::from_file(path, base_url).await
+        } else if let Ok(input) = input.parse::<Url>() {
+            if input.scheme() == "file" {
+                let path = input
+                    .to_file_path()
+                    .map_err(|_| anyhow!("{} is not a valid local file path.", input))?;
+                return Self::from_file(&path, base_url).await;
             }
 
-            Ok(Self {
-                url: base_url.unwrap().clone(),
-                data: fs::read(path).await?,
-                playlist_type: typ,
-            })
-        } else if let Ok(input) = input.parse::<Url>() {
             let response = client.get(input).query(query).send().await?;
 
             if let Some(content_type) = response.headers().get(header::CONTENT_TYPE) {
@@ -68,6 +62,26 @@ impl FetchedPlaylist {
         }
     }
 
+    /// Read a local playlist file. Relative segment paths are resolved against
+    /// the playlist's own directory unless a base url is provided.
+    async fn from_file(path: &Path, base_url: Option<&Url>) -> Result<Self> {
+        let url = match base_url {
+            Some(base_url) => base_url.clone(),
+            None => Url::from_file_path(path.canonicalize()?)
+                .map_err(|_| anyhow!("{} is not a valid local file path.", path.display()))?,
+        };
+
+        Ok(Self {
+            url,
+            data: fs::read(path).await?,
+            playlist_type: match path.extension() {
+                Some(ext) if ext == "m3u" || ext == "m3u8" => Some(PlaylistType::Hls),
+                Some(ext) if ext == "mpd" => Some(PlaylistType::Dash),
+                _ => None,
+            },
+        })
+    }
+
     fn playlist_type(&self) -> Result<PlaylistType> {
         if let Some(typ) = &self.playlist_type {
             return Ok(typ.to_owned());
@@ -81,53 +95,97 @@ impl FetchedPlaylist {
         bail!("Unable to determine the input playlist type.");
     }
 
-    pub fn list_streams(&self) -> Result<()> {
+    async fn parse_dash(
+        &self,
+        cache: &Cache,
+        client: &Client,
+        query: &Vec<(String, String)>,
+    ) -> Result<MPD> {
+        let xml = String::from_utf8_lossy(&self.data);
+        let mut mpd =
+            dash_mpd::parse(&xml).map_err(|e| anyhow!("Failed to parse DASH playlist: {e}"))?;
+        crate::dash::resolve_xlinks(&mut mpd, &self.url, cache, client, query).await?;
+        Ok(mpd)
+    }
+
+    /// Duration of a vod hls presentation, taken from its first media playlist. Playlists are
+    /// cached, so the selected stream isn't requested twice.
+    async fn hls_duration(
+        &self,
+        playlist: &MasterPlaylist,
+        cache: &Cache,
+        client: &Client,
+        query: &Vec<(String, String)>,
+    ) -> Option<f32> {
+        let stream = playlist.streams.first()?;
+        let url = self.url.join(&stream.uri).ok()?;
+        let data = cache.fetch(client, url.clone(), query, None).await.ok()?;
+        let (data, _) = crate::hls::substitute(&data, &url, &stream.variables).ok()?;
+        let media_playlist = m3u8_rs::parse_media_playlist_res(&data).ok()?;
+
+        if !media_playlist.end_list {
+            return None;
+        }
+
+        Some(media_playlist.segments.iter().map(|x| x.duration).sum())
+    }
+
+    pub async fn list_streams(
+        &self,
+        cache: &Cache,
+        client: &Client,
+        query: &Vec<(String, String)>,
+    ) -> Result<()> {
         match self.playlist_type()? {
             PlaylistType::Dash => {
-                let xml = String::from_utf8_lossy(&self.data);
-                let mpd = dash_mpd::parse(&xml)
-                    .map_err(|e| anyhow!("Failed to parse DASH playlist: {e}"))?;
+                let mpd = self.parse_dash(cache, client, query).await?;
                 crate::dash::parse_as_master(&mpd, self.url.as_ref())
                     .sort_streams()
                     .list_streams();
             }
-            PlaylistType::Hls => match m3u8_rs::parse_playlist_res(&self.data)
-                .map_err(|e| anyhow!("Failed to parse HLS playlist: {e}"))?
-            {
-                m3u8_rs::Playlist::MasterPlaylist(m3u8) => {
-                    crate::hls::parse_as_master(&m3u8, self.url.as_ref())
-                        .sort_streams()
-                        .list_streams()
-                }
-                m3u8_rs::Playlist::MediaPlaylist(_) => {
-                    info!("------ {} ------", "Undefined Streams".cyan());
-                    info!(" 1) {}", self.url);
+            PlaylistType::Hls => {
+                let (data, variables) =
+                    crate::hls::substitute(&self.data, &self.url, &HashMap::new())?;
+
+                match m3u8_rs::parse_playlist_res(&data)
+                    .map_err(|e| anyhow!("Failed to parse HLS playlist: {e}"))?
+                {
+                    m3u8_rs::Playlist::MasterPlaylist(m3u8) => {
+                        crate::hls::parse_as_master(&m3u8, self.url.as_ref(), &variables)
+                            .sort_streams()
+                            .list_streams()
+                    }
+                    m3u8_rs::Playlist::MediaPlaylist(_) => {
+                        info!("------ {} ------", "Undefined Streams".cyan());
+                        info!(" 1) {}", self.url);
+                    }
                 }
-            },
+            }
         }
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn as_master_playlist(
         &self,
+        cache: &Cache,
         client: &Client,
         query: &Vec<(String, String)>,
         mut select_opts: SelectOptions,
         interaction: Interaction,
+        mux: &MuxOptions,
         parse_everything: bool,
     ) -> Result<MasterPlaylist> {
         match self.playlist_type()? {
             PlaylistType::Dash => {
-                let xml = String::from_utf8_lossy(&self.data);
-                let mpd = dash_mpd::parse(&xml)
-                    .map_err(|e| anyhow!("Failed to parse DASH playlist: {e}"))?;
+                let mpd = self.parse_dash(cache, client, query).await?;
 
                 let mut playlist = if parse_everything {
                     crate::dash::parse_as_master(&mpd, self.url.as_str())
                 } else {
                     crate::dash::parse_as_master(&mpd, self.url.as_str())
                         .sort_streams()
-                        .select_streams(&mut select_opts, interaction)?
+                        .select_streams(&mut select_opts, interaction, mux)?
                 };
 
                 for stream in &mut playlist.streams {
@@ -137,52 +195,86 @@ impl FetchedPlaylist {
 
                 Ok(playlist)
             }
-            PlaylistType::Hls => match m3u8_rs::parse_playlist_res(&self.data)
-                .map_err(|e| anyhow!("Failed to parse HLS playlist: {e}"))?
-            {
-                m3u8_rs::Playlist::MasterPlaylist(playlist) => {
-                    let mut playlist = if parse_everything {
-                        crate::hls::parse_as_master(&playlist, self.url.as_str())
-                    } else {
-                        crate::hls::parse_as_master(&playlist, self.url.as_str())
-                            .sort_streams()
-                            .select_streams(&mut select_opts, interaction)?
-                    };
-
-                    for stream in &mut playlist.streams {
-                        let data;
-                        if let Some(bs) = stream
-                            .uri
-                            .strip_prefix("data:application/x-mpegurl;base64,")
-                        {
-                            data = base64::engine::general_purpose::STANDARD.decode(bs)?;
-                        } else {
-                            stream.uri = self.url.join(&stream.uri)?.to_string();
-                            let response = client.get(&stream.uri).query(query).send().await?;
-                            data = utils::fetch_bytes(response).await?;
+            PlaylistType::Hls => {
+                let (data, variables) =
+                    crate::hls::substitute(&self.data, &self.url, &HashMap::new())?;
+
+                match m3u8_rs::parse_playlist_res(&data)
+                    .map_err(|e| anyhow!("Failed to parse HLS playlist: {e}"))?
+                {
+                    m3u8_rs::Playlist::MasterPlaylist(playlist) => {
+                        let mut playlist =
+                            crate::hls::parse_as_master(&playlist, self.url.as_str(), &variables);
+
+                        if !parse_everything {
+                            if matches!(interaction, Interaction::Modern) {
+                                playlist.duration =
+                                    self.hls_duration(&playlist, cache, client, query).await;
+                            }
+
+                            playlist = playlist.sort_streams().select_streams(
+                                &mut select_opts,
+                                interaction,
+                                mux,
+                            )?;
                         }
 
-                        let media_playlist = m3u8_rs::parse_media_playlist_res(&data)
-                            .map_err(|e| anyhow!("Failed to parse HLS playlist: {e}"))?;
-                        crate::hls::push_segments(&media_playlist, stream);
+                        for stream in &mut playlist.streams {
+                            let mut url = self.url.join(&stream.uri)?;
+                            let mut data = cache.fetch(client, url.clone(), query, None).await?;
+
+                            // Delta updates (EXT-X-SKIP) only list the most recent segments.
+                            if crate::hls::is_delta(&data) {
+                                url = crate::hls::full_playlist_url(&url);
+                                data = cache.fetch(client, url.clone(), query, None).await?;
+                            }
+
+                            // Segments of inlined playlists are resolved against the master playlist.
+                            stream.uri = if url.scheme() == "data" {
+                                self.url.to_string()
+                            } else {
+                                url.to_string()
+                            };
+
+                            let (data, _) = crate::hls::substitute(&data, &url, &stream.variables)?;
+                            let media_playlist = m3u8_rs::parse_media_playlist_res(&data)
+                                .map_err(|e| anyhow!("Failed to parse HLS playlist: {e}"))?;
+                            crate::hls::push_segments(&media_playlist, stream);
+
+                            // Inlined playlists cannot be reloaded, so they are never followed live.
+                            if url.scheme() == "data" {
+                                stream.live = false;
+                            }
+                        }
+
+                        Ok(playlist)
                     }
+                    m3u8_rs::Playlist::MediaPlaylist(mut playlist) => {
+                        let mut url = self.url.clone();
 
-                    Ok(playlist)
-                }
-                m3u8_rs::Playlist::MediaPlaylist(playlist) => {
-                    let mut media_playlist = MediaPlaylist {
-                        id: utils::gen_id(self.url.as_str(), ""),
-                        uri: self.url.as_str().to_owned(),
-                        ..Default::default()
-                    };
-                    crate::hls::push_segments(&playlist, &mut media_playlist);
-                    Ok(MasterPlaylist {
-                        playlist_type: PlaylistType::Hls,
-                        streams: vec![media_playlist],
-                        uri: self.url.as_str().to_owned(),
-                    })
+                        if crate::hls::is_delta(&data) {
+                            url = crate::hls::full_playlist_url(&url);
+                            let data = cache.fetch(client, url.clone(), query, None).await?;
+                            let (data, _) = crate::hls::substitute(&data, &url, &HashMap::new())?;
+                            playlist = m3u8_rs::parse_media_playlist_res(&data)
+                                .map_err(|e| anyhow!("Failed to parse HLS playlist: {e}"))?;
+                        }
+
+                        let mut media_playlist = MediaPlaylist {
+                            id: utils::gen_id(self.url.as_str(), ""),
+                            uri: url.to_string(),
+                            ..Default::default()
+                        };
+                        crate::hls::push_segments(&playlist, &mut media_playlist);
+                        Ok(MasterPlaylist {
+                            duration: media_playlist.duration(),
+                            playlist_type: PlaylistType::Hls,
+                            streams: vec![media_playlist],
+                            uri: self.url.as_str().to_owned(),
+                        })
+                    }
                 }
-            },
+            }
         }
     }
 }
@@ -0,0 +1,124 @@
+use crate::{
+    playlist::MediaPlaylist,
+    progress::{self, ByteSize},
+};
+use anyhow::Result;
+use colored::Colorize;
+use log::{info, warn};
+use serde_json::json;
+use std::{env, path::PathBuf, process::ExitStatus, sync::atomic::Ordering, time::Instant};
+use tokio::process::Command;
+
+/// Job details exposed to `--exec` and `--on-error` commands as environment variables.
+///
+/// - `VSD_INPUT`: input playlist.
+/// - `VSD_OUTPUT`: muxed output path or downloaded stream paths (joined like `PATH`).
+/// - `VSD_STREAMS`: selected streams as JSON, like `--parse` without segments.
+/// - `VSD_ELAPSED`: elapsed job time in seconds.
+/// - `VSD_BYTES`: downloaded bytes.
+/// - `VSD_ERROR`: error message (only for `--on-error`).
+pub struct Hook {
+    input: String,
+    pub outputs: Vec<PathBuf>,
+    pub streams: serde_json::Value,
+    timer: Instant,
+}
+
+impl Hook {
+    pub fn new(input: &str) -> Self {
+        progress::DOWNLOADED_BYTES.store(0, Ordering::SeqCst);
+
+        Self {
+            input: input.to_owned(),
+            outputs: Vec::new(),
+            streams: json!([]),
+            timer: Instant::now(),
+        }
+    }
+
+    /// Streams in the `--parse` format, without their segments which could exceed the size
+    /// limit of an environment variable.
+    pub fn set_streams(&mut self, streams: &[MediaPlaylist]) {
+        self.streams = streams
+            .iter()
+            .filter_map(|x| serde_json::to_value(x).ok())
+            .map(|mut x| {
+                if let Some(x) = x.as_object_mut() {
+                    x.remove("segments");
+                }
+                x
+            })
+            .collect();
+    }
+
+    /// Run a hook command. `{input}` and `{output}` expand to the quoted `VSD_INPUT` and
+    /// `VSD_OUTPUT` variables, so that their values are never parsed by the shell.
+    pub async fn run(&self, cmd: &str, error: Option<&anyhow::Error>) -> Result<ExitStatus> {
+        let output = env::join_paths(&self.outputs)?;
+
+        #[cfg(target_os = "windows")]
+        let mut command = Command::new("cmd");
+        // Passed as is, since cmd doesn't understand the escaped quotes of regular arguments.
+        #[cfg(target_os = "windows")]
+        command.arg("/C").raw_arg(
+            cmd.replace("{input}", "\"%VSD_INPUT%\"")
+                .replace("{output}", "\"%VSD_OUTPUT%\""),
+        );
+        #[cfg(not(target_os = "windows"))]
+        let mut command = Command::new("sh");
+        #[cfg(not(target_os = "windows"))]
+        command.arg("-c").arg(
+            cmd.replace("{input}", "\"$VSD_INPUT\"")
+                .replace("{output}", "\"$VSD_OUTPUT\""),
+        );
+
+        command
+            .env("VSD_INPUT", &self.input)
+            .env("VSD_OUTPUT", &output)
+            .env("VSD_STREAMS", self.streams.to_string())
+            .env(
+                "VSD_ELAPSED",
+                format!("{:.3}", self.timer.elapsed().as_secs_f64()),
+            )
+            .env(
+                "VSD_BYTES",
+                progress::DOWNLOADED_BYTES
+                    .load(Ordering::SeqCst)
+                    .to_string(),
+            );
+
+        if let Some(error) = error {
+            command.env("VSD_ERROR", error.to_string());
+        }
+
+        let tag = if error.is_some() { "err" } else { "exe" };
+        info!("Hooked [{}] {}", tag.cyan(), cmd);
+        Ok(command.status().await?)
+    }
+
+    /// Final job summary, with the exit status of the hook which was run.
+    pub fn report(&self, failed: bool, status: Option<&Result<ExitStatus>>) {
+        let mut summary = format!(
+            "{} in {:.1}s",
+            ByteSize(progress::DOWNLOADED_BYTES.load(Ordering::SeqCst)),
+            self.timer.elapsed().as_secs_f64()
+        );
+
+        match status.map(|x| x.as_ref().map(|x| x.code())) {
+            Some(Ok(Some(code))) => summary.push_str(&format!(", hook exited with code {}", code)),
+            Some(Ok(None)) => summary.push_str(", hook terminated by signal"),
+            Some(Err(e)) => summary.push_str(&format!(", hook failed to start ({})", e)),
+            None => (),
+        }
+
+        let hook_failed = status.is_some_and(|x| !x.as_ref().is_ok_and(|x| x.success()));
+
+        if failed {
+            warn!("Failed [{}] {}", "job".red(), summary);
+        } else if hook_failed {
+            warn!("Finish [{}] {}", "job".yellow(), summary);
+        } else {
+            info!("Finish [{}] {}", "job".green(), summary);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{
+    downloader::{MAX_RETRIES, RUNNING, stream::TaskBuilder},
+    hls::LowLatency,
+    playlist::{MediaPlaylist, Part},
+    request::Request,
+};
+use anyhow::{Result, anyhow, bail};
+use log::{debug, warn};
+use reqwest::Url;
+use std::{sync::atomic::Ordering, time::Duration};
+
+/// Keep recording a low-latency hls stream until the playlist ends or Ctrl+C is pressed.
+///
+/// Parts of the segment being produced are downloaded as soon as they are listed or hinted,
+/// and assembled into the full segment once it completes. Playlist reloads block on the server
+/// (`_HLS_msn`/`_HLS_part`) when it supports them. Returns the new number of segments.
+pub(super) async fn follow(builder: &mut TaskBuilder<'_>, mut total: usize) -> Result<usize> {
+    let url = crate::hls::full_playlist_url(&builder.stream.uri.parse::<Url>()?);
+    let mut msn = builder.stream.media_sequence + builder.stream.segments.len() as u64;
+    let mut parts: Vec<(Part, Vec<u8>)> = Vec::new();
+    let mut skip_parts = false;
+    let mut low_latency = LowLatency::default();
+
+    while RUNNING.load(Ordering::SeqCst) {
+        let reload = if !low_latency.can_block_reload {
+            // Playlists without a target duration are reloaded at least a second apart.
+            let wait = low_latency
+                .part_target
+                .unwrap_or((low_latency.target_duration / 2.0).max(1.0));
+            tokio::time::sleep(Duration::from_secs_f32(wait)).await;
+            url.clone()
+        } else if skip_parts {
+            // Blocking on a part which failed would return immediately, so wait for a segment.
+            low_latency.blocking_url(&url, msn + 1, None)
+        } else {
+            low_latency.blocking_url(&url, msn, Some(parts.len()))
+        };
+
+        let (data, playlist) = reload_playlist(builder, reload, &url).await?;
+        low_latency = LowLatency::new(&data);
+
+        let mut stream = MediaPlaylist::default();
+        crate::hls::push_segments(&playlist, &mut stream);
+
+        // Delta updates list segments after the skipped ones.
+        let first_sequence = playlist.media_sequence + low_latency.skipped_segments;
+
+        for (sequence, segment) in (first_sequence..).zip(&stream.segments) {
+            if sequence < msn {
+                continue;
+            }
+
+            if sequence > msn {
+                warn!(
+                    "Segments {}..{} left the live window before they could be downloaded.",
+                    msn, sequence
+                );
+                msn = sequence;
+                parts.clear();
+            }
+
+            // Reuse the downloaded parts when they make up the whole segment.
+            let data = (!parts.is_empty()
+                && parts.len() == segment.parts.len()
+                && parts
+                    .iter()
+                    .zip(&segment.parts)
+                    .all(|(x, y)| x.0.uri == y.uri && x.0.range == y.range))
+            .then(|| parts.iter().flat_map(|x| x.1.clone()).collect());
+
+            builder.build(total, segment, data).await?.execute().await?;
+            total += 1;
+            msn += 1;
+            parts.clear();
+            skip_parts = false;
+        }
+
+        if playlist.end_list {
+            break;
+        }
+
+        if skip_parts || first_sequence + stream.segments.len() as u64 != msn {
+            continue;
+        }
+
+        for part in low_latency.next_parts(parts.len()) {
+            let url = builder.base_url.join(&part.uri)?;
+
+            match Request::new(builder.client, url, builder.query, part.range.as_ref())?
+                .send()
+                .await
+            {
+                Ok(bytes) => parts.push((part, bytes)),
+                Err(error) => {
+                    // The full segment is downloaded instead.
+                    debug!("{}", error);
+                    parts.clear();
+                    skip_parts = true;
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(total)
+}
+
+/// Reload the live playlist, retrying failed requests and partially written playlists with
+/// exponential backoff, up to the maximum number of retries.
+async fn reload_playlist(
+    builder: &TaskBuilder<'_>,
+    reload: Url,
+    url: &Url,
+) -> Result<(Vec<u8>, m3u8_rs::MediaPlaylist)> {
+    let mut delay = Duration::from_millis(500);
+
+    for _ in 0..MAX_RETRIES.load(Ordering::SeqCst) {
+        let result = match Request::new(builder.client, reload.clone(), builder.query, None)?
+            .send()
+            .await
+        {
+            Ok(data) => crate::hls::substitute(&data, url, &builder.stream.variables).and_then(
+                |(data, _)| {
+                    let playlist = m3u8_rs::parse_media_playlist_res(&data)
+                        .map_err(|e| anyhow!("Failed to parse HLS playlist: {e}"));
+                    playlist.map(|playlist| (data, playlist))
+                },
+            ),
+            Err(error) => Err(error),
+        };
+
+        match result {
+            Ok(result) => return Ok(result),
+            Err(error) => {
+                warn!(
+                    "Playlist reload failed, retrying in {:.1}s. {}",
+                    delay.as_secs_f32(),
+                    error
+                );
+                tokio::time::sleep(delay).await;
+                delay = (delay * 2).min(Duration::from_secs(10));
+            }
+        }
+    }
+
+    bail!("Exceeded the maximum retry limit while reloading the live playlist.");
+}
@@ -1,18 +1,22 @@
 mod encryption;
 mod fetch;
 mod fix;
+mod hook;
+mod live;
 mod mux;
 mod stream;
 mod subtitle;
 
 pub use fetch::FetchedPlaylist;
+pub(crate) use mux::{Stream, Streams};
 pub use subtitle::download_subtitle_streams;
 use vsd_mp4::pssh::PsshBox;
 
 use crate::{
-    downloader::mux::Streams,
-    options::{Interaction, SelectOptions},
+    downloader::hook::Hook,
+    options::{Interaction, MuxOptions, SelectOptions},
     playlist::MediaType,
+    request::Cache,
     utils,
 };
 use anyhow::{Result, bail};
@@ -43,6 +47,11 @@ pub struct Downloader {
     select_options: SelectOptions,
     query: Vec<(String, String)>,
     keys: HashMap<String, String>,
+    cache: Cache,
+    exec: Option<String>,
+    on_error: Option<String>,
+    fill_gaps: bool,
+    start_at_offset: bool,
 }
 
 impl Downloader {
@@ -58,12 +67,18 @@ impl Downloader {
             select_options: "v=best:s=en".parse().unwrap(),
             query: Vec::new(),
             keys: HashMap::new(),
+            cache: Cache::default(),
+            exec: None,
+            on_error: None,
+            fill_gaps: false,
+            start_at_offset: false,
         }
     }
 
     /// Base URL for resolving relative segment paths.
     ///
-    /// Required for local playlist files. For remote playlists,
+    /// Accepts `http(s)://` and `file://` URLs. For local playlist files,
+    /// the playlist's own directory is used by default. For remote playlists,
     /// the final redirected URL is used by default.
     pub fn base_url(mut self, base_url: impl Into<Url>) -> Self {
         self.base_url = Some(base_url.into());
@@ -81,6 +96,7 @@ impl Downloader {
     /// Mux downloaded streams into a video container using ffmpeg (`.mp4`, `.mkv`, etc.).
     ///
     /// Overwrites existing files and deletes intermediate stream files after muxing.
+    /// Use `-` to write a single stream as-is, or multiple streams muxed as matroska, to stdout.
     pub fn output(mut self, output: impl Into<PathBuf>) -> Self {
         self.output = Some(output.into());
         self
@@ -162,18 +178,58 @@ impl Downloader {
         self
     }
 
+    /// Command to run after a successful download and mux.
+    ///
+    /// `{input}` and `{output}` placeholders expand to the quoted `VSD_INPUT` and
+    /// `VSD_OUTPUT` variables and job details are exposed as `VSD_*` environment variables.
+    pub fn exec(mut self, cmd: impl Into<String>) -> Self {
+        self.exec = Some(cmd.into());
+        self
+    }
+
+    /// Command to run when the download fails, with `VSD_ERROR` set to the error message.
+    pub fn on_error(mut self, cmd: impl Into<String>) -> Self {
+        self.on_error = Some(cmd.into());
+        self
+    }
+
+    /// Fill gap segments (`EXT-X-GAP`) with silence and repeated frames when muxing.
+    ///
+    /// Streams having gaps are re-encoded with ffmpeg.
+    pub fn fill_gaps(mut self, fill_gaps: bool) -> Self {
+        self.fill_gaps = fill_gaps;
+        self
+    }
+
+    /// Start downloading at the playlist's preferred start point (`EXT-X-START`).
+    pub fn start_at_offset(mut self, start_at_offset: bool) -> Self {
+        self.start_at_offset = start_at_offset;
+        self
+    }
+
+    fn mux_options(&self) -> MuxOptions {
+        MuxOptions {
+            fill_gaps: self.fill_gaps,
+            output: self.output.clone(),
+            subs_codec: self.subs_codec.clone(),
+        }
+    }
+
     async fn fetch_playlist(&self) -> Result<FetchedPlaylist> {
-        Ok(FetchedPlaylist::new(
+        FetchedPlaylist::new(
             &self.input,
             &self.client,
             self.base_url.as_ref(),
             &self.query,
         )
-        .await?)
+        .await
     }
 
     pub(crate) async fn list_playlist(self) -> Result<()> {
-        self.fetch_playlist().await?.list_streams()?;
+        self.fetch_playlist()
+            .await?
+            .list_streams(&self.cache, &self.client, &self.query)
+            .await?;
         Ok(())
     }
 
@@ -182,10 +238,12 @@ impl Downloader {
             .fetch_playlist()
             .await?
             .as_master_playlist(
+                &self.cache,
                 &self.client,
                 &self.query,
                 self.select_options,
                 Interaction::None,
+                &MuxOptions::default(),
                 true,
             )
             .await?;
@@ -202,47 +260,89 @@ impl Downloader {
             .fetch_playlist()
             .await?
             .as_master_playlist(
+                &self.cache,
                 &self.client,
                 &self.query,
                 self.select_options,
                 Interaction::None,
+                &MuxOptions::default(),
                 true,
             )
             .await?;
 
         let mut pssh_data = HashSet::new();
         for stream in pl.streams {
-            let Some(init_seg) = stream.fetch_init_seg(&self.client, &self.query).await? else {
-                continue;
-            };
-            PsshBox::from_init(&init_seg)?
-                .data
-                .into_iter()
-                .for_each(|x| {
-                    let _ = pssh_data.insert(x.data);
-                });
+            for init_seg in stream
+                .fetch_init_segs(&self.cache, &self.client, &self.query)
+                .await?
+            {
+                PsshBox::from_init(&init_seg)?
+                    .data
+                    .into_iter()
+                    .for_each(|x| {
+                        let _ = pssh_data.insert(x.data);
+                    });
+            }
         }
         Ok(pssh_data)
     }
 
-    pub async fn download(self) -> Result<()> {
+    pub async fn download(mut self) -> Result<()> {
+        let exec = self.exec.take();
+        let on_error = self.on_error.take();
+        let mut hook = Hook::new(&self.input);
+        let result = self.run(&mut hook).await;
+
+        let (cmd, error) = match &result {
+            Ok(()) => (exec, None),
+            Err(e) => (on_error, Some(e)),
+        };
+
+        let status = match &cmd {
+            Some(cmd) => Some(hook.run(cmd, error).await),
+            None => None,
+        };
+
+        hook.report(error.is_some(), status.as_ref());
+
+        // The download error takes precedence over a hook which failed to start.
+        match (result, status) {
+            (Ok(()), Some(Err(e))) => Err(e),
+            (result, _) => result,
+        }
+    }
+
+    async fn run(self, hook: &mut Hook) -> Result<()> {
+        let mux_options = self.mux_options();
         let pl = self
             .fetch_playlist()
             .await?
             .as_master_playlist(
+                &self.cache,
                 &self.client,
                 &self.query,
                 self.select_options,
                 self.interaction_type,
+                &mux_options,
                 false,
             )
             .await?;
         let mut streams = pl.streams;
+        hook.set_streams(&streams);
+
+        // Multiple streams are muxed to stdout by ffmpeg, which fails when they can't be muxed.
+        if self.output.as_ref().is_some_and(|x| x.as_os_str() == "-")
+            && !mux::should_pipe(&streams, self.output.as_ref())
+            && !mux::should_mux(&streams, self.output.as_ref())
+        {
+            bail!("--output - requires a single stream or streams which can be muxed together.");
+        }
 
         if !SKIP_DECRYPT.load(Ordering::SeqCst) {
             encryption::check_unsupported_enc(&streams)?;
             let default_kids =
-                encryption::get_default_kids(&streams, &self.client, &self.query).await?;
+                encryption::get_default_kids(&streams, &self.cache, &self.client, &self.query)
+                    .await?;
             encryption::check_keys_exist(&self.keys, &default_kids)?;
         }
 
@@ -252,6 +352,10 @@ impl Downloader {
                     .fetch_split_seg(&self.base_url, &self.client, &self.query)
                     .await?;
             }
+
+            if self.start_at_offset {
+                stream.seek_to_start();
+            }
         }
 
         tokio::spawn(async {
@@ -267,6 +371,7 @@ impl Downloader {
         });
 
         let mut temp_files = Streams(Vec::new());
+        let pipe = mux::should_pipe(&streams, self.output.as_ref());
 
         if let Some(directory) = &self.directory
             && !directory.exists()
@@ -286,23 +391,37 @@ impl Downloader {
 
         stream::download_streams(
             &self.base_url,
+            &self.cache,
             &self.client,
             self.directory.as_ref(),
             &self.keys,
+            pipe,
             &self.query,
             &streams,
             &mut temp_files.0,
         )
         .await?;
 
-        if mux::should_mux(&streams, self.output.as_ref()) {
+        if pipe {
+            temp_files.clean(self.directory.as_ref()).await?;
+            hook.outputs.push(PathBuf::from("-"));
+        } else if mux::should_mux(&streams, self.output.as_ref()) {
             let Some(ffmpeg) = utils::find_ffmpeg() else {
                 bail!("ffmpeg couldn't be located, it's required to continue further.");
             };
             temp_files
-                .mux(&ffmpeg, self.output.as_ref().unwrap(), &self.subs_codec)
+                .mux(
+                    &ffmpeg,
+                    self.output.as_ref().unwrap(),
+                    &self.subs_codec,
+                    self.fill_gaps,
+                )
                 .await?;
             temp_files.clean(self.directory.as_ref()).await?;
+            hook.outputs.push(self.output.clone().unwrap());
+        } else {
+            hook.outputs
+                .extend(temp_files.0.into_iter().map(|x| x.path));
         }
 
         Ok(())
@@ -1,23 +1,78 @@
 use crate::{
     downloader::{SKIP_DECRYPT, SKIP_MERGE},
+    language::Language,
     playlist::{MediaPlaylist, MediaType},
 };
 use anyhow::{Result, bail};
 use colored::Colorize;
 use log::{info, warn};
-use std::{ffi::OsStr, path::PathBuf, process::Stdio, sync::atomic::Ordering};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::atomic::Ordering,
+};
 use tokio::{fs, process::Command};
 
 pub struct Streams(pub Vec<Stream>);
 
 pub struct Stream {
+    /// Ffmpeg `-disposition` flags.
+    pub dispositions: Vec<&'static str>,
+    /// Stream has gap segments which weren't downloaded.
+    pub gaps: bool,
     pub language: Option<String>,
     pub media_type: MediaType,
     pub path: PathBuf,
 }
 
+impl Stream {
+    /// Language as an ISO 639-2 code, which is what containers expect.
+    fn iso_language(&self) -> Option<String> {
+        self.language
+            .as_ref()
+            .map(|x| Language::parse(x).map_or_else(|| x.to_owned(), |x| x.iso639_2()))
+    }
+}
+
 impl Streams {
-    pub async fn mux(&self, ffmpeg: &PathBuf, output: &PathBuf, subs_codec: &str) -> Result<()> {
+    pub async fn mux(
+        &self,
+        ffmpeg: &Path,
+        output: &Path,
+        subs_codec: &str,
+        fill_gaps: bool,
+    ) -> Result<()> {
+        let args = self.args(output, subs_codec, fill_gaps);
+
+        info!(
+            "Muxing [{}] ffmpeg {}",
+            "exe".cyan(),
+            args.iter()
+                .map(|x| if x.contains(' ') {
+                    format!("\"{x}\"")
+                } else {
+                    x.to_owned()
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+
+        let status = Command::new(ffmpeg)
+            .args(args)
+            .stderr(Stdio::null())
+            .status()
+            .await?;
+
+        if !status.success() {
+            bail!("ffmpeg exited with code {}", status.code().unwrap_or(1));
+        }
+
+        Ok(())
+    }
+
+    /// Ffmpeg arguments which mux the streams into `output`.
+    pub fn args(&self, output: &Path, subs_codec: &str, fill_gaps: bool) -> Vec<String> {
         let sub_streams_present = self
             .0
             .iter()
@@ -57,29 +112,53 @@ impl Streams {
                 args.extend_from_slice(&["-map".to_owned(), i.to_string()]);
             }
 
+            // The first subtitle stream is the default one, unless the playlist picks one.
+            let default_sub_present = temp_files.iter().any(|x| {
+                x.media_type == MediaType::Subtitles && x.dispositions.contains(&"default")
+            });
             let mut audio_index = 0;
             let mut subtitle_index = 0;
 
             for temp_file in &temp_files {
                 match temp_file.media_type {
                     MediaType::Audio => {
-                        if let Some(language) = &temp_file.language {
+                        if let Some(language) = temp_file.iso_language() {
                             args.extend_from_slice(&[
                                 format!("-metadata:s:a:{audio_index}"),
                                 format!("language={language}"),
                             ]);
                         }
 
+                        if !temp_file.dispositions.is_empty() {
+                            args.extend_from_slice(&[
+                                format!("-disposition:a:{audio_index}"),
+                                temp_file.dispositions.join("+"),
+                            ]);
+                        }
+
                         audio_index += 1;
                     }
                     MediaType::Subtitles => {
-                        if let Some(language) = &temp_file.language {
+                        if let Some(language) = temp_file.iso_language() {
                             args.extend_from_slice(&[
                                 format!("-metadata:s:s:{subtitle_index}"),
                                 format!("language={language}"),
                             ]);
                         }
 
+                        let mut dispositions = temp_file.dispositions.clone();
+
+                        if subtitle_index == 0 && !default_sub_present {
+                            dispositions.insert(0, "default");
+                        }
+
+                        if !dispositions.is_empty() {
+                            args.extend_from_slice(&[
+                                format!("-disposition:s:{subtitle_index}"),
+                                dispositions.join("+"),
+                            ]);
+                        }
+
                         subtitle_index += 1;
                     }
                     _ => (),
@@ -87,8 +166,6 @@ impl Streams {
             }
 
             if sub_streams_present {
-                args.extend_from_slice(&["-disposition:s:0".to_owned(), "default".to_owned()]);
-
                 if subs_codec == "copy" {
                     if output.extension() == Some(OsStr::new("mp4")) {
                         args.extend_from_slice(&["-c:s".to_owned(), "mov_text".to_owned()]);
@@ -108,32 +185,49 @@ impl Streams {
             ]);
         }
 
-        args.push(output.to_string_lossy().into());
+        if fill_gaps {
+            // Re-encoded streams fill the gaps with silence or repeated frames.
+            let mut audio_index = 0;
+            let mut video_index = 0;
 
-        info!(
-            "Muxing [{}] ffmpeg {}",
-            "exe".cyan(),
-            args.iter()
-                .map(|x| if x.contains(' ') {
-                    format!("\"{x}\"")
-                } else {
-                    x.to_owned()
-                })
-                .collect::<Vec<_>>()
-                .join(" ")
-        );
+            for temp_file in &temp_files {
+                match temp_file.media_type {
+                    MediaType::Audio => {
+                        if temp_file.gaps {
+                            args.extend_from_slice(&[
+                                format!("-filter:a:{audio_index}"),
+                                "aresample=async=1:first_pts=0".to_owned(),
+                                format!("-c:a:{audio_index}"),
+                                "aac".to_owned(),
+                            ]);
+                        }
 
-        let status = Command::new(ffmpeg)
-            .args(args)
-            .stderr(Stdio::null())
-            .status()
-            .await?;
+                        audio_index += 1;
+                    }
+                    MediaType::Video => {
+                        if temp_file.gaps {
+                            args.extend_from_slice(&[
+                                format!("-fps_mode:v:{video_index}"),
+                                "cfr".to_owned(),
+                                format!("-c:v:{video_index}"),
+                                "libx264".to_owned(),
+                            ]);
+                        }
 
-        if !status.success() {
-            bail!("ffmpeg exited with code {}", status.code().unwrap_or(1));
+                        video_index += 1;
+                    }
+                    _ => (),
+                }
+            }
         }
 
-        Ok(())
+        // Stdout has no extension to guess the container from and needs a streamable one.
+        if output.as_os_str() == "-" {
+            args.extend_from_slice(&["-f".to_owned(), "matroska".to_owned()]);
+        }
+
+        args.push(output.to_string_lossy().into());
+        args
     }
 
     pub async fn clean(&self, directory: Option<&PathBuf>) -> Result<()> {
@@ -159,6 +253,13 @@ impl Streams {
     }
 }
 
+/// A single non-subtitle stream written to stdout is piped as-is, without muxing.
+pub fn should_pipe(streams: &[MediaPlaylist], output: Option<&PathBuf>) -> bool {
+    output.is_some_and(|x| x.as_os_str() == "-")
+        && streams.len() == 1
+        && streams[0].media_type != MediaType::Subtitles
+}
+
 pub fn should_mux(streams: &[MediaPlaylist], output: Option<&PathBuf>) -> bool {
     if output.is_none() {
         return false;
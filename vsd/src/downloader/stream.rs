@@ -1,22 +1,25 @@
 use crate::{
     downloader::{
-        MAX_RETRIES, MAX_THREADS, SKIP_DECRYPT, SKIP_MERGE, encryption::Decrypter, fix, mux::Stream,
+        MAX_RETRIES, MAX_THREADS, SKIP_DECRYPT, SKIP_MERGE, encryption::Decrypter, fix, live,
+        mux::Stream,
     },
-    playlist::{KeyMethod, MediaPlaylist, MediaType},
+    playlist::{Key, KeyMethod, Map, MediaPlaylist, MediaType, Segment},
     progress::Progress,
+    request::{Cache, Request},
 };
 use anyhow::{Result, anyhow, bail};
 use colored::Colorize;
-use log::{debug, error, info, warn};
-use reqwest::{Client, RequestBuilder, StatusCode, Url, header};
+use log::{debug, info, warn};
+use reqwest::{Client, StatusCode, Url};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::PathBuf,
     sync::{Arc, atomic::Ordering},
 };
 use tokio::{
     fs::{self, File},
     io::{self, AsyncWriteExt},
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
     task::JoinSet,
 };
 use vsd_mp4::{
@@ -28,19 +31,23 @@ use vsd_mp4::{
 #[allow(clippy::too_many_arguments)]
 pub async fn download_streams(
     base_url: &Option<Url>,
+    cache: &Cache,
     client: &Client,
     directory: Option<&PathBuf>,
     keys: &HashMap<String, String>,
+    pipe: bool,
     query: &Vec<(String, String)>,
-    streams: &Vec<MediaPlaylist>,
+    streams: &[MediaPlaylist],
     temp_files: &mut Vec<Stream>,
 ) -> Result<()> {
     let streams = streams
-        .into_iter()
+        .iter()
         .filter(|x| x.media_type != MediaType::Subtitles)
         .collect::<Vec<_>>();
+    // Live low-latency streams never wait for each other, they are recorded side by side.
+    let mut live_set = JoinSet::new();
 
-    for stream in streams {
+    for (i, stream) in streams.into_iter().enumerate() {
         info!(
             "DownLD [{}] {}",
             stream.media_type.to_string().green(),
@@ -54,22 +61,58 @@ pub async fn download_streams(
 
         let temp_file = stream.path(directory);
 
-        temp_files.push(Stream {
-            language: stream.language.clone(),
-            media_type: stream.media_type.clone(),
-            path: temp_file.clone(),
-        });
+        if pipe {
+            info!("Saving [{}] stdout", stream.media_type.to_string().green());
+        } else {
+            temp_files.push(Stream {
+                dispositions: stream.dispositions(),
+                gaps: stream.segments.iter().any(|x| x.gap),
+                language: stream.language.clone(),
+                media_type: stream.media_type.clone(),
+                path: temp_file.clone(),
+            });
+
+            info!(
+                "Saving [{}] {}",
+                stream.media_type.to_string().green(),
+                temp_file.with_extension("").to_string_lossy()
+            );
+        }
+
+        if stream.live && stream.low_latency {
+            let (base_url, cache, client, keys, query, stream) = (
+                base_url.clone(),
+                cache.clone(),
+                client.clone(),
+                keys.clone(),
+                query.clone(),
+                stream.clone(),
+            );
+
+            live_set.spawn(async move {
+                download_stream(
+                    &base_url,
+                    &cache,
+                    &client,
+                    &keys,
+                    Progress::new(&i.to_string(), stream.segments.len()),
+                    pipe,
+                    &query,
+                    &stream,
+                    &temp_file,
+                )
+                .await
+            });
+            continue;
+        }
 
-        info!(
-            "Saving [{}] {}",
-            stream.media_type.to_string().green(),
-            temp_file.with_extension("").to_string_lossy()
-        );
         download_stream(
             base_url,
+            cache,
             client,
             keys,
             Progress::new("0", stream.segments.len()),
+            pipe,
             query,
             stream,
             &temp_file,
@@ -77,6 +120,10 @@ pub async fn download_streams(
         .await?;
     }
 
+    while let Some(result) = live_set.join_next().await {
+        result??;
+    }
+
     // eprintln!();
     Ok(())
 }
@@ -84,9 +131,11 @@ pub async fn download_streams(
 #[allow(clippy::too_many_arguments)]
 async fn download_stream(
     base_url: &Option<Url>,
+    cache: &Cache,
     client: &Client,
     keys: &HashMap<String, String>,
     pb: Progress,
+    pipe: bool,
     query: &Vec<(String, String)>,
     stream: &MediaPlaylist,
     temp_file: &PathBuf,
@@ -96,15 +145,19 @@ async fn download_stream(
         .unwrap_or(stream.uri.parse::<Url>().unwrap());
     let mut tasks = Vec::with_capacity(stream.segments.len());
 
-    let mut decrypter = Decrypter::None;
     let temp_dir = temp_file.with_extension("");
     let extension = stream.extension();
-    let total = stream.segments.len();
-    let should_decrypt = !SKIP_DECRYPT.load(Ordering::SeqCst);
-    let mut increment_media_sequence = false;
-    let mut media_sequence = stream.media_sequence;
+    let mut total = stream.segments.len();
+    let follow = stream.live && stream.low_latency;
+    let gaps = stream.segments.iter().filter(|x| x.gap).count();
+
+    if gaps > 0 {
+        warn!("{} gap segments (EXT-X-GAP) will be skipped.", gaps);
+    }
+
     let media_type = stream.media_type.to_string();
-    let init_seg = stream.fetch_init_seg(client, query).await?;
+    let init_seg = stream.fetch_init_seg(cache, client, query).await?;
+    let (sender, receiver) = mpsc::unbounded_channel();
 
     let default_kid = if let Some(init_seg) = &init_seg {
         TencBox::from_init(init_seg)?.map(|x| x.default_kid_hex())
@@ -112,127 +165,72 @@ async fn download_stream(
         stream.default_kid()
     };
 
-    for (i, segment) in stream.segments.iter().enumerate() {
-        if should_decrypt {
-            if decrypter.is_hls() && segment.key.is_none() && increment_media_sequence {
-                decrypter.increment_iv();
-                media_sequence += 1;
-            }
-
-            if let Some(key) = &segment.key {
-                match key.method {
-                    KeyMethod::Aes128 | KeyMethod::SampleAes => {
-                        match key.method {
-                            KeyMethod::Aes128 => {
-                                decrypter = Decrypter::Aes128(HlsAes128Decrypter::new(
-                                    &key.key(&base_url, client, query).await?,
-                                    &key.iv(media_sequence)?,
-                                ));
-                            }
-                            KeyMethod::SampleAes => {
-                                decrypter = Decrypter::SampleAes(HlsSampleAesDecrypter::new(
-                                    &key.key(&base_url, client, query).await?,
-                                    &key.iv(media_sequence)?,
-                                ));
-                            }
-                            _ => (),
-                        }
-
-                        if key.iv.is_none() {
-                            increment_media_sequence = true;
-                            media_sequence += 1;
-                        } else {
-                            increment_media_sequence = false;
-                        }
-                    }
-                    KeyMethod::Cenc => {
-                        if keys.is_empty() {
-                            bail!("Custom keys are required to proceed further.");
-                        }
-
-                        let default_kid = default_kid.as_ref().ok_or_else(|| {
-                            anyhow!("Unable to determine the default KID for this stream.")
-                        })?;
-
-                        let mut key = None;
-
-                        if keys.contains_key(default_kid) {
-                            key = Some(keys.get(default_kid).unwrap().to_owned())
-                        } else {
-                            warn!(
-                                "No key provided for ({}:?); checking PSSH data to identify other mappable KIDs.",
-                                default_kid
-                            );
-
-                            if let Some(init_seg) = &init_seg {
-                                for kid in PsshBox::from_init(init_seg)?
-                                    .data
-                                    .into_iter()
-                                    .map(|x| x.key_ids)
-                                    .flatten()
-                                {
-                                    if keys.contains_key(&kid.0) {
-                                        key = Some(keys.get(&kid.0).unwrap().to_owned());
-                                    }
-                                }
-                            }
-                        }
-
-                        let key = key.ok_or_else(|| {
-                            anyhow!("Unable to determine the key for this stream.")
-                        })?;
-
-                        decrypter = Decrypter::Cenc(Arc::new(
-                            CencDecryptingProcessor::builder()
-                                .key(default_kid, &key)?
-                                .build()?,
-                        ));
-
-                        info!("DrmKey [{}] {}:{}", "dec".magenta(), default_kid, key);
-                    }
-                    _ => (),
-                }
-            }
-        }
-
-        let url = base_url.join(&segment.uri)?;
-        let mut request = client.get(url).query(query);
-
-        if let Some(range) = &segment.range {
-            request = request.header(header::RANGE, range);
-        }
+    let mut builder = TaskBuilder {
+        base_url,
+        cache,
+        client,
+        decrypter: Decrypter::None,
+        default_kid,
+        done: pipe.then_some(sender),
+        extension: extension.to_owned(),
+        increment_media_sequence: false,
+        init_seg,
+        key: None,
+        keys,
+        map: None,
+        media_sequence: stream.media_sequence,
+        pb,
+        query,
+        should_decrypt: !SKIP_DECRYPT.load(Ordering::SeqCst),
+        stream,
+        temp_dir: temp_dir.clone(),
+    };
 
-        tasks.push(Task {
-            decrypter: decrypter.clone(),
-            init_seg: init_seg.clone(),
-            pb: pb.clone(),
-            request,
-            temp_file: temp_dir.join(format!("{}.{}.part", i, extension)),
-        });
+    for (i, segment) in stream.segments.iter().enumerate() {
+        tasks.push(builder.build(i, segment, None).await?);
     }
 
     fs::create_dir_all(&temp_dir).await?;
 
+    let writer = if pipe {
+        Some(tokio::spawn(pipe_segments(
+            receiver,
+            temp_dir.clone(),
+            extension.to_owned(),
+            // Live streams are written until the last segment is sent.
+            if follow { usize::MAX } else { total },
+        )))
+    } else {
+        None
+    };
+
     let mut set = JoinSet::new();
     let max_threads = MAX_THREADS.load(Ordering::SeqCst) as usize;
 
     for task in tasks {
         while set.len() >= max_threads {
-            set.join_next().await;
-        }
-        set.spawn(async move {
-            if let Err(e) = task.execute().await {
-                error!("{}", e);
-                std::process::exit(1);
+            if let Some(result) = set.join_next().await {
+                result??;
             }
-        });
+        }
+        set.spawn(task.execute());
     }
 
-    while let Some(_res) = set.join_next().await {}
+    while let Some(result) = set.join_next().await {
+        result??;
+    }
 
+    if follow {
+        total = live::follow(&mut builder, total).await?;
+    }
+
+    drop(builder);
     eprintln!();
 
-    if SKIP_MERGE.load(Ordering::SeqCst) {
+    if let Some(writer) = writer {
+        writer.await??;
+        fs::remove_dir_all(&temp_dir).await?;
+    } else if SKIP_MERGE.load(Ordering::SeqCst) {
         info!("Mergin [{}] skipped", media_type.red());
     } else {
         info!(
@@ -261,17 +259,245 @@ async fn download_stream(
     Ok(())
 }
 
-struct Task {
+/// Write completed segments to stdout in playlist order, as soon as they are available.
+async fn pipe_segments(
+    mut receiver: UnboundedReceiver<usize>,
+    temp_dir: PathBuf,
+    extension: String,
+    total: usize,
+) -> Result<()> {
+    let mut stdout = io::stdout();
+    let mut completed = HashSet::new();
+    let mut next = 0;
+
+    while next < total {
+        let Some(i) = receiver.recv().await else {
+            break;
+        };
+        completed.insert(i);
+
+        while completed.remove(&next) {
+            let path = temp_dir.join(format!("{}.{}", next, extension));
+            next += 1;
+
+            // Gap segments aren't written.
+            if !path.exists() {
+                continue;
+            }
+
+            io::copy(&mut File::open(&path).await?, &mut stdout).await?;
+            stdout.flush().await?;
+            fs::remove_file(&path).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Turns segments into download tasks, carrying the active init segment and decrypter
+/// from one segment to the next.
+pub(super) struct TaskBuilder<'a> {
+    pub(super) base_url: Url,
+    cache: &'a Cache,
+    pub(super) client: &'a Client,
+    decrypter: Decrypter,
+    default_kid: Option<String>,
+    done: Option<UnboundedSender<usize>>,
+    extension: String,
+    increment_media_sequence: bool,
+    init_seg: Option<Arc<Vec<u8>>>,
+    /// Last key tag, reused when an init segment with a different default KID appears.
+    key: Option<Key>,
+    keys: &'a HashMap<String, String>,
+    /// Active init map.
+    map: Option<Map>,
+    media_sequence: u64,
+    pb: Progress,
+    pub(super) query: &'a Vec<(String, String)>,
+    should_decrypt: bool,
+    pub(super) stream: &'a MediaPlaylist,
+    temp_dir: PathBuf,
+}
+
+impl TaskBuilder<'_> {
+    /// Build the task for the `i`th segment. `data` replaces the segment request, e.g. with
+    /// already downloaded parts.
+    pub(super) async fn build(
+        &mut self,
+        i: usize,
+        segment: &Segment,
+        data: Option<Vec<u8>>,
+    ) -> Result<Task> {
+        let (cache, client, query) = (self.cache, self.client, self.query);
+        self.pb.set_total(i + 1);
+
+        // Dash periods and hls EXT-X-MAP tags switch the init segment for the segments that follow.
+        if let Some(map) = &segment.map
+            && self.map.as_ref() != Some(map)
+        {
+            if i > 0 {
+                let data = self.stream.fetch_map(map, cache, client, query).await?;
+                let default_kid = TencBox::from_init(&data)?.map(|x| x.default_kid_hex());
+                self.init_seg = Some(data);
+
+                // Content keyed differently needs a new decrypter even if the key tag isn't repeated.
+                if default_kid.is_some() && default_kid != self.default_kid {
+                    self.default_kid = default_kid;
+
+                    if self.should_decrypt
+                        && segment.key.is_none()
+                        && let Some(key) = self.key.clone()
+                        && key.method == KeyMethod::Cenc
+                    {
+                        self.set_key(&key).await?;
+                    }
+                }
+            }
+
+            self.map = Some(map.clone());
+        }
+
+        if let Some(key) = &segment.key {
+            self.key = Some(key.clone());
+        }
+
+        if self.should_decrypt {
+            if self.decrypter.is_hls() && segment.key.is_none() && self.increment_media_sequence {
+                self.decrypter.increment_iv();
+                self.media_sequence += 1;
+            }
+
+            if let Some(key) = &segment.key {
+                self.set_key(key).await?;
+            }
+        }
+
+        let request = match data {
+            _ if segment.gap => None,
+            Some(data) => Some(Request::Data(data)),
+            None => Some(Request::new(
+                client,
+                self.base_url.join(&segment.uri)?,
+                query,
+                segment.range.as_ref(),
+            )?),
+        };
+
+        Ok(Task {
+            decrypter: self.decrypter.clone(),
+            done: self.done.clone().map(|x| (i, x)),
+            // Webm clusters can't repeat the ebml header, so it's only written where an init starts.
+            init_seg: if self.extension == "webm" && i > 0 && segment.map.is_none() {
+                None
+            } else {
+                self.init_seg.clone()
+            },
+            pb: self.pb.clone(),
+            request,
+            temp_file: self.temp_dir.join(format!("{}.{}.part", i, self.extension)),
+        })
+    }
+
+    async fn set_key(&mut self, key: &Key) -> Result<()> {
+        let (cache, client, query) = (self.cache, self.client, self.query);
+
+        match key.method {
+            KeyMethod::Aes128 | KeyMethod::SampleAes => {
+                match key.method {
+                    KeyMethod::Aes128 => {
+                        self.decrypter = Decrypter::Aes128(HlsAes128Decrypter::new(
+                            &key.key(&self.base_url, cache, client, query).await?,
+                            &key.iv(self.media_sequence)?,
+                        ));
+                    }
+                    KeyMethod::SampleAes => {
+                        self.decrypter = Decrypter::SampleAes(HlsSampleAesDecrypter::new(
+                            &key.key(&self.base_url, cache, client, query).await?,
+                            &key.iv(self.media_sequence)?,
+                        ));
+                    }
+                    _ => (),
+                }
+
+                if key.iv.is_none() {
+                    self.increment_media_sequence = true;
+                    self.media_sequence += 1;
+                } else {
+                    self.increment_media_sequence = false;
+                }
+            }
+            KeyMethod::Cenc => {
+                if self.keys.is_empty() {
+                    bail!("Custom keys are required to proceed further.");
+                }
+
+                let default_kid = self.default_kid.as_ref().ok_or_else(|| {
+                    anyhow!("Unable to determine the default KID for this stream.")
+                })?;
+
+                let mut key = None;
+
+                if self.keys.contains_key(default_kid) {
+                    key = Some(self.keys.get(default_kid).unwrap().to_owned())
+                } else {
+                    warn!(
+                        "No key provided for ({}:?); checking PSSH data to identify other mappable KIDs.",
+                        default_kid
+                    );
+
+                    if let Some(init_seg) = &self.init_seg {
+                        for kid in PsshBox::from_init(init_seg)?
+                            .data
+                            .into_iter()
+                            .flat_map(|x| x.key_ids)
+                        {
+                            if self.keys.contains_key(&kid.0) {
+                                key = Some(self.keys.get(&kid.0).unwrap().to_owned());
+                            }
+                        }
+                    }
+                }
+
+                let key =
+                    key.ok_or_else(|| anyhow!("Unable to determine the key for this stream."))?;
+
+                self.decrypter = Decrypter::Cenc(Arc::new(
+                    CencDecryptingProcessor::builder()
+                        .key(default_kid, &key)?
+                        .build()?,
+                ));
+
+                info!("DrmKey [{}] {}:{}", "dec".magenta(), default_kid, key);
+            }
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+pub(super) struct Task {
     decrypter: Decrypter,
+    done: Option<(usize, UnboundedSender<usize>)>,
     init_seg: Option<Arc<Vec<u8>>>,
     pb: Progress,
-    request: RequestBuilder,
+    /// Gap segments have no request and don't produce a file.
+    request: Option<Request>,
     temp_file: PathBuf,
 }
 
 impl Task {
-    async fn execute(self) -> Result<()> {
-        let segment = self.segment().await?;
+    pub(super) async fn execute(self) -> Result<()> {
+        let Some(request) = &self.request else {
+            if let Some((i, sender)) = &self.done {
+                let _ = sender.send(*i);
+            }
+
+            self.pb.update(0);
+            return Ok(());
+        };
+
+        let segment = self.segment(request).await?;
         let segment_bytes = segment.len();
         let segment = fix::fake_png_header(segment);
         let segment = self
@@ -288,27 +514,23 @@ impl Task {
         f.flush().await?;
         fs::rename(&self.temp_file, self.temp_file.with_extension("")).await?;
 
+        if let Some((i, sender)) = &self.done {
+            let _ = sender.send(*i);
+        }
+
         self.pb.update(segment_bytes);
         Ok(())
     }
 
-    async fn segment(&self) -> Result<Vec<u8>> {
+    async fn segment(&self, request: &Request) -> Result<Vec<u8>> {
         for _ in 0..MAX_RETRIES.load(Ordering::SeqCst) {
-            let response = match self.request.try_clone().unwrap().send().await {
-                Ok(response) => response,
-                Err(error) => {
-                    debug!("{}", check(&error)?);
-                    continue;
-                }
-            };
-
-            let status = response.status();
-            if status.is_client_error() || status.is_server_error() {
-                bail!("{} (http {})", response.url(), status);
+            match request.send().await {
+                Ok(bytes) => return Ok(bytes),
+                Err(error) => match error.downcast_ref::<reqwest::Error>() {
+                    Some(error) => debug!("{}", check(error)?),
+                    None => return Err(error),
+                },
             }
-
-            let bytes = response.bytes().await?;
-            return Ok(bytes.to_vec());
         }
 
         bail!("Exceeded the maximum retry limit while downloading a segment.");
@@ -2,15 +2,15 @@ use super::{MAX_THREADS, mux::Stream};
 use crate::{
     playlist::{MediaPlaylist, MediaType},
     progress::Progress,
-    utils,
+    request::Request,
 };
 use anyhow::Result;
 use colored::Colorize;
-use log::{debug, error, info, warn};
-use reqwest::{Client, Url, header};
+use log::{debug, info, warn};
+use reqwest::{Client, Url};
 use std::{path::PathBuf, sync::atomic::Ordering};
 use tokio::{fs::File, io::AsyncWriteExt, task::JoinSet};
-use vsd_mp4::text::{Mp4TtmlParser, Mp4VttParser, ttml_text_parser};
+use vsd_mp4::text::{Mp4TtmlParser, Mp4VttParser, Subtitles, ttml_text_parser};
 
 enum SubtitleType {
     Mp4Vtt,
@@ -100,40 +100,48 @@ async fn download_subtitle_stream(
     let base_url = base_url
         .clone()
         .unwrap_or(stream.uri.parse::<Url>().unwrap());
-    let segment = &stream.segments[0];
-    let mut data = Vec::new();
     let ext = stream.extension();
     let mut temp_file = stream.path(directory);
 
-    if let Some(map) = &segment.map {
-        let url = base_url.join(&map.uri)?;
-        let mut request = client.get(url).query(query);
+    // Dash periods and hls EXT-X-MAP tags switch the init segment for the segments that follow,
+    // so segments are grouped by the init they are parsed with.
+    let mut maps = Vec::new();
+    let mut runs = Vec::with_capacity(stream.segments.len());
 
-        if let Some(range) = &map.range {
-            request = request.header(header::RANGE, range);
+    for segment in &stream.segments {
+        if let Some(map) = &segment.map
+            && maps.last() != Some(&map)
+        {
+            maps.push(map);
         }
 
-        let response = request.send().await?;
-        let mut bytes = utils::fetch_bytes(response).await?;
-        data.append(&mut bytes);
+        runs.push(maps.len().saturating_sub(1));
     }
 
-    let url = base_url.join(&segment.uri)?;
-    let mut request = client.get(url).query(query);
+    let mut data = vec![Vec::new(); maps.len().max(1)];
 
-    if let Some(range) = &segment.range {
-        request = request.header(header::RANGE, range);
+    for (i, map) in maps.iter().enumerate() {
+        let url = base_url.join(&map.uri)?;
+        let mut bytes = Request::new(client, url, query, map.range.as_ref())?
+            .send()
+            .await?;
+        data[i].append(&mut bytes);
     }
 
-    let response = request.send().await?;
-    let mut bytes = utils::fetch_bytes(response).await?;
+    let segment = &stream.segments[0];
+    let url = base_url.join(&segment.uri)?;
+    let mut bytes = Request::new(client, url, query, segment.range.as_ref())?
+        .send()
+        .await?;
     let size = bytes.len();
-    data.append(&mut bytes);
+    data[0].append(&mut bytes);
 
-    let (ext, codec) = detect_codec(stream.codecs.as_deref(), &data, ext);
+    let (ext, codec) = detect_codec(stream.codecs.as_deref(), &data[0], ext);
 
     temp_file = temp_file.with_extension(ext);
     temp_files.push(Stream {
+        dispositions: stream.dispositions(),
+        gaps: false,
         language: stream.language.clone(),
         media_type: stream.media_type.clone(),
         path: temp_file.clone(),
@@ -145,45 +153,33 @@ async fn download_subtitle_stream(
 
     if !remaining.is_empty() {
         let max_threads = MAX_THREADS.load(Ordering::SeqCst) as usize;
-        let mut set: JoinSet<(usize, Vec<u8>)> = JoinSet::new();
+        let mut set: JoinSet<Result<(usize, Vec<u8>)>> = JoinSet::new();
         let mut results = vec![None; remaining.len()];
 
         for (i, segment) in remaining.iter().enumerate() {
             while set.len() >= max_threads {
-                if let Some(Ok((i, bytes))) = set.join_next().await {
+                if let Some(result) = set.join_next().await {
+                    let (i, bytes) = result??;
                     pb.update(bytes.len());
                     results[i] = Some(bytes);
                 }
             }
 
             let url = base_url.join(&segment.uri)?;
-            let mut request = client.get(url).query(query);
+            let request = Request::new(client, url, query, segment.range.as_ref())?;
 
-            if let Some(range) = &segment.range {
-                request = request.header(header::RANGE, range);
-            }
-
-            set.spawn(async move {
-                let response = request.send().await.unwrap_or_else(|e| {
-                    error!("{}", e);
-                    std::process::exit(1);
-                });
-                let bytes = utils::fetch_bytes(response).await.unwrap_or_else(|e| {
-                    error!("{}", e);
-                    std::process::exit(1);
-                });
-                (i, bytes)
-            });
+            set.spawn(async move { Ok((i, request.send().await?)) });
         }
 
-        while let Some(Ok((i, bytes))) = set.join_next().await {
+        while let Some(result) = set.join_next().await {
+            let (i, bytes) = result??;
             pb.update(bytes.len());
             results[i] = Some(bytes);
         }
 
-        for result in results {
-            if let Some(mut bytes) = result {
-                data.append(&mut bytes);
+        for (i, bytes) in results.into_iter().enumerate() {
+            if let Some(mut bytes) = bytes {
+                data[runs[i + 1]].append(&mut bytes);
             }
         }
     }
@@ -193,28 +189,37 @@ async fn download_subtitle_stream(
     let output = match codec {
         SubtitleType::Mp4Vtt => {
             debug!("Extracting wvtt subtitles.");
-            let vtt = Mp4VttParser::from_init(&data)?;
-            vtt.parse(&data, None)?.as_vtt().into_bytes()
+            let mut subtitles = Subtitles::default();
+
+            for data in &data {
+                let vtt = Mp4VttParser::from_init(data)?;
+                subtitles.extend(vtt.parse(data, None)?);
+            }
+
+            subtitles.as_vtt().into_bytes()
         }
         SubtitleType::Mp4Ttml => {
             debug!("Extracting stpp subtitles.");
-            let ttml = Mp4TtmlParser::from_init(&data)?;
-            ttml.parse(&data)?.as_srt().into_bytes()
+            let mut subtitles = Subtitles::default();
+
+            for data in &data {
+                let ttml = Mp4TtmlParser::from_init(data)?;
+                subtitles.extend(ttml.parse(data)?);
+            }
+
+            subtitles.as_srt().into_bytes()
         }
         SubtitleType::TtmlText => {
             debug!("Extracting ttml+xml subtitles.");
-            ttml_text_parser::parse_bytes(&data)?
+            ttml_text_parser::parse_bytes(&data.concat())?
                 .into_subtitles()
                 .as_srt()
                 .into_bytes()
         }
-        _ => data,
+        _ => data.concat(),
     };
 
-    File::create(&temp_file)
-        .await?
-        .write_all(&output)
-        .await?;
+    File::create(&temp_file).await?.write_all(&output).await?;
 
     Ok(())
 }
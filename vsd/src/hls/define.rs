@@ -0,0 +1,198 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.3
+    2. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.4.2.3
+
+*/
+
+use super::attributes;
+use anyhow::{Result, bail};
+use reqwest::Url;
+use std::collections::HashMap;
+
+/// Apply the `EXT-X-DEFINE` variables of a playlist before it is parsed.
+///
+/// `imports` are the variables defined by the master playlist and `url` is the playlist location,
+/// whose query string provides `QUERYPARAM` variables. Returns the playlist with every `{$name}`
+/// reference replaced along with the variables it defines. Variables which can't be resolved and
+/// references to undefined ones fail the playlist, as the spec requires.
+pub(crate) fn substitute(
+    data: &[u8],
+    url: &Url,
+    imports: &HashMap<String, String>,
+) -> Result<(Vec<u8>, HashMap<String, String>)> {
+    let mut variables = HashMap::new();
+
+    if !data.windows(14).any(|x| x == b"#EXT-X-DEFINE:") && !data.windows(2).any(|x| x == b"{$") {
+        return Ok((data.to_vec(), variables));
+    }
+
+    let text = String::from_utf8_lossy(data);
+    let mut output = String::with_capacity(text.len());
+
+    for line in text.split_inclusive('\n') {
+        let Some(list) = line.trim().strip_prefix("#EXT-X-DEFINE:") else {
+            output.push_str(&replace(line, &variables)?);
+            continue;
+        };
+
+        let attributes = attributes(list);
+        let variable =
+            if let (Some(name), Some(value)) = (attributes.get("NAME"), attributes.get("VALUE")) {
+                Some((name.to_owned(), value.to_owned()))
+            } else if let Some(name) = attributes.get("IMPORT") {
+                imports.get(name).map(|x| (name.to_owned(), x.to_owned()))
+            } else if let Some(name) = attributes.get("QUERYPARAM") {
+                url.query_pairs()
+                    .find(|x| x.0 == name.as_str())
+                    .map(|x| (name.to_owned(), x.1.into_owned()))
+            } else {
+                None
+            };
+
+        let Some((name, value)) = variable else {
+            bail!("Unable to resolve hls variable ({}).", list);
+        };
+
+        variables.insert(name, value);
+        output.push_str(line);
+    }
+
+    Ok((output.into_bytes(), variables))
+}
+
+/// Replace `{$name}` references with their values.
+fn replace(line: &str, variables: &HashMap<String, String>) -> Result<String> {
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find("{$") {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+
+        match variables.get(&rest[2..end]) {
+            Some(value) => output.push_str(value),
+            None => bail!("Hls variable {} is used but never defined.", &rest[..=end]),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(url: &str) -> Url {
+        url.parse().unwrap()
+    }
+
+    fn text(data: Vec<u8>) -> String {
+        String::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn name_value() {
+        let (data, variables) = substitute(
+            b"#EXTM3U\n#EXT-X-DEFINE:NAME=\"host\",VALUE=\"https://cdn.example.com\"\n#EXTINF:4,\n{$host}/a-{$host}.ts\n",
+            &url("https://example.com/media.m3u8"),
+            &HashMap::new(),
+        )
+        .unwrap();
+
+        assert!(
+            text(data)
+                .ends_with("#EXTINF:4,\nhttps://cdn.example.com/a-https://cdn.example.com.ts\n")
+        );
+        assert_eq!(variables["host"], "https://cdn.example.com");
+    }
+
+    #[test]
+    fn query_param() {
+        let (data, _) = substitute(
+            b"#EXT-X-DEFINE:QUERYPARAM=\"token\"\nseg.ts?token={$token}\n",
+            &url("https://example.com/media.m3u8?id=1&token=a%20b"),
+            &HashMap::new(),
+        )
+        .unwrap();
+
+        assert!(text(data).ends_with("seg.ts?token=a b\n"));
+        assert!(
+            substitute(
+                b"#EXT-X-DEFINE:QUERYPARAM=\"token\"\n",
+                &url("https://example.com/media.m3u8?id=1"),
+                &HashMap::new(),
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn import() {
+        let (_, imports) = substitute(
+            b"#EXTM3U\n#EXT-X-DEFINE:NAME=\"auth\",VALUE=\"key=1\"\n#EXT-X-STREAM-INF:BANDWIDTH=1\nmedia.m3u8?{$auth}\n",
+            &url("https://example.com/master.m3u8"),
+            &HashMap::new(),
+        )
+        .unwrap();
+        let (data, variables) = substitute(
+            b"#EXT-X-DEFINE:IMPORT=\"auth\"\nseg.ts?{$auth}\n",
+            &url("https://example.com/media.m3u8"),
+            &imports,
+        )
+        .unwrap();
+
+        assert!(text(data).ends_with("seg.ts?key=1\n"));
+        assert_eq!(variables["auth"], "key=1");
+
+        // Variables of the master playlist must be imported explicitly.
+        assert!(
+            substitute(
+                b"#EXT-X-DEFINE:IMPORT=\"other\"\n",
+                &url("https://example.com/media.m3u8"),
+                &imports,
+            )
+            .is_err()
+        );
+        assert!(
+            substitute(
+                b"seg.ts?{$auth}\n",
+                &url("https://example.com/media.m3u8"),
+                &imports,
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn undefined() {
+        // A reference must follow the definition of its variable.
+        assert!(
+            substitute(
+                b"{$host}/a.ts\n#EXT-X-DEFINE:NAME=\"host\",VALUE=\"x\"\n",
+                &url("https://example.com/media.m3u8"),
+                &HashMap::new(),
+            )
+            .is_err()
+        );
+
+        let data = b"#EXTM3U\n#EXTINF:4,\na.ts\n";
+        let (output, variables) = substitute(
+            data,
+            &url("https://example.com/media.m3u8"),
+            &HashMap::new(),
+        )
+        .unwrap();
+        assert_eq!(output, data);
+        assert!(variables.is_empty());
+    }
+}
@@ -0,0 +1,306 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.4.3.7
+    2. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-4.4.5.2
+    3. https://datatracker.ietf.org/doc/html/draft-pantos-hls-rfc8216bis#section-6.2.5.2
+
+*/
+
+use super::attributes;
+use crate::playlist::{Part, Range};
+use reqwest::Url;
+
+/// Low-latency tags of a media playlist, which `m3u8_rs` doesn't understand.
+#[derive(Default)]
+pub(crate) struct LowLatency {
+    /// `EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES`
+    pub can_block_reload: bool,
+    /// `EXT-X-PART-INF:PART-TARGET`
+    pub part_target: Option<f32>,
+    /// Parts listed after the last complete segment, i.e. of the segment being produced.
+    pub pending_parts: Vec<Part>,
+    /// `EXT-X-PRELOAD-HINT:TYPE=PART`
+    pub preload_hint: Option<Part>,
+    /// `EXT-X-SKIP:SKIPPED-SEGMENTS` of a delta update.
+    pub skipped_segments: u64,
+    /// `EXT-X-TARGETDURATION`
+    pub target_duration: f32,
+}
+
+impl LowLatency {
+    pub(crate) fn new(data: &[u8]) -> Self {
+        let mut low_latency = Self::default();
+        let mut previous_end = 0;
+
+        for line in String::from_utf8_lossy(data).lines() {
+            let line = line.trim();
+
+            if let Some(value) = line.strip_prefix("#EXT-X-TARGETDURATION:") {
+                low_latency.target_duration = value.parse().unwrap_or(0.0);
+            } else if let Some(list) = line.strip_prefix("#EXT-X-SERVER-CONTROL:") {
+                low_latency.can_block_reload = attributes(list)
+                    .get("CAN-BLOCK-RELOAD")
+                    .is_some_and(|x| x == "YES");
+            } else if let Some(list) = line.strip_prefix("#EXT-X-PART-INF:") {
+                low_latency.part_target = attributes(list)
+                    .get("PART-TARGET")
+                    .and_then(|x| x.parse().ok());
+            } else if let Some(list) = line.strip_prefix("#EXT-X-PART:") {
+                low_latency
+                    .pending_parts
+                    .extend(part(list, &mut previous_end));
+            } else if let Some(list) = line.strip_prefix("#EXT-X-SKIP:") {
+                low_latency.skipped_segments = attributes(list)
+                    .get("SKIPPED-SEGMENTS")
+                    .and_then(|x| x.parse().ok())
+                    .unwrap_or(0);
+            } else if let Some(list) = line.strip_prefix("#EXT-X-PRELOAD-HINT:") {
+                low_latency.preload_hint = preload_hint(list);
+            } else if !line.is_empty() && !line.starts_with('#') {
+                // A segment uri completes the parts listed before it.
+                low_latency.pending_parts.clear();
+            }
+        }
+
+        low_latency
+    }
+
+    /// Playlist url which blocks on the server until segment `msn`, or its `part` when given,
+    /// is available (`_HLS_msn`/`_HLS_part`).
+    pub(crate) fn blocking_url(&self, url: &Url, msn: u64, part: Option<usize>) -> Url {
+        let mut url = url.clone();
+        url.query_pairs_mut()
+            .append_pair("_HLS_msn", &msn.to_string());
+
+        if let Some(part) = part.filter(|_| self.part_target.is_some()) {
+            url.query_pairs_mut()
+                .append_pair("_HLS_part", &part.to_string());
+        }
+
+        url
+    }
+
+    /// Parts which are yet to be downloaded, after the `downloaded` ones, followed by the
+    /// preload hint when it isn't listed already. Byte range parts share a uri, so the range
+    /// is compared too.
+    pub(crate) fn next_parts(&mut self, downloaded: usize) -> Vec<Part> {
+        let hint = self.preload_hint.take().filter(|x| {
+            !self
+                .pending_parts
+                .iter()
+                .any(|y| y.uri == x.uri && y.range == x.range)
+        });
+
+        self.pending_parts
+            .drain(..)
+            .skip(downloaded)
+            .chain(hint)
+            .collect()
+    }
+}
+
+/// Whether the playlist is a delta update (`EXT-X-SKIP`), which omits older segments.
+pub(crate) fn is_delta(data: &[u8]) -> bool {
+    data.windows(12).any(|x| x == b"#EXT-X-SKIP:")
+}
+
+/// Remove the delivery directives (`_HLS_msn`, `_HLS_part` and `_HLS_skip`) from a playlist url,
+/// so that the full playlist is requested.
+pub(crate) fn full_playlist_url(url: &Url) -> Url {
+    let mut url = url.clone();
+    let pairs = url
+        .query_pairs()
+        .filter(|x| !x.0.starts_with("_HLS_"))
+        .map(|x| (x.0.into_owned(), x.1.into_owned()))
+        .collect::<Vec<_>>();
+
+    if pairs.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+
+    url
+}
+
+/// Parse the attribute list of an `EXT-X-PART` tag. A byte range without an offset continues
+/// from `previous_end`.
+pub(crate) fn part(list: &str, previous_end: &mut u64) -> Option<Part> {
+    let attributes = attributes(list);
+
+    let range = attributes.get("BYTERANGE").and_then(|x| {
+        let (length, offset) = match x.split_once('@') {
+            Some((length, offset)) => (length.parse::<u64>().ok()?, offset.parse().ok()?),
+            None => (x.parse::<u64>().ok()?, *previous_end),
+        };
+
+        *previous_end = offset + length;
+        Some(Range {
+            end: offset + length - 1,
+            start: offset,
+        })
+    });
+
+    Some(Part {
+        duration: attributes.get("DURATION")?.parse().ok()?,
+        independent: attributes.get("INDEPENDENT").is_some_and(|x| x == "YES"),
+        range,
+        uri: attributes.get("URI")?.to_owned(),
+    })
+}
+
+fn preload_hint(list: &str) -> Option<Part> {
+    let attributes = attributes(list);
+
+    if attributes.get("TYPE").map(|x| x.as_str()) != Some("PART") {
+        return None;
+    }
+
+    let start = attributes
+        .get("BYTERANGE-START")
+        .and_then(|x| x.parse::<u64>().ok())
+        .unwrap_or(0);
+    let length = attributes
+        .get("BYTERANGE-LENGTH")
+        .and_then(|x| x.parse::<u64>().ok());
+
+    let range = match length {
+        Some(length) => Some(Range {
+            end: start + length - 1,
+            start,
+        }),
+        // An open ended range can't be requested ahead of time.
+        None if start > 0 => return None,
+        None => None,
+    };
+
+    Some(Part {
+        duration: 0.0,
+        independent: false,
+        range,
+        uri: attributes.get("URI")?.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAYLIST: &[u8] = b"#EXTM3U
+#EXT-X-TARGETDURATION:4
+#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK=3.0
+#EXT-X-PART-INF:PART-TARGET=1.0
+#EXT-X-MEDIA-SEQUENCE:10
+#EXT-X-PART:DURATION=1.0,URI=\"s10.0.m4s\",INDEPENDENT=YES
+#EXT-X-PART:DURATION=1.0,URI=\"s10.1.m4s\"
+#EXTINF:4.0,
+s10.m4s
+#EXT-X-PART:DURATION=1.0,URI=\"s11.m4s\",BYTERANGE=\"100@0\",INDEPENDENT=YES
+#EXT-X-PART:DURATION=1.0,URI=\"s11.m4s\",BYTERANGE=\"150\"
+#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"s11.m4s\",BYTERANGE-START=250,BYTERANGE-LENGTH=120
+";
+
+    fn range(start: u64, end: u64) -> Option<Range> {
+        Some(Range { end, start })
+    }
+
+    #[test]
+    fn parse_tags() {
+        let low_latency = LowLatency::new(PLAYLIST);
+
+        assert!(low_latency.can_block_reload);
+        assert_eq!(low_latency.part_target, Some(1.0));
+        assert_eq!(low_latency.target_duration, 4.0);
+        assert_eq!(low_latency.skipped_segments, 0);
+
+        // Parts of the completed s10 segment are dropped.
+        assert_eq!(low_latency.pending_parts.len(), 2);
+        assert!(low_latency.pending_parts[0].independent);
+        assert_eq!(low_latency.pending_parts[0].range, range(0, 99));
+        assert_eq!(low_latency.pending_parts[1].range, range(100, 249));
+
+        let hint = low_latency.preload_hint.unwrap();
+        assert_eq!(hint.uri, "s11.m4s");
+        assert_eq!(hint.range, range(250, 369));
+    }
+
+    #[test]
+    fn parse_delta_update() {
+        let data = b"#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-SKIP:SKIPPED-SEGMENTS=12\n";
+        let low_latency = LowLatency::new(data);
+
+        assert!(is_delta(data));
+        assert!(!is_delta(PLAYLIST));
+        assert!(!low_latency.can_block_reload);
+        assert_eq!(low_latency.part_target, None);
+        assert_eq!(low_latency.skipped_segments, 12);
+    }
+
+    #[test]
+    fn next_parts() {
+        let mut low_latency = LowLatency::new(PLAYLIST);
+        let parts = low_latency.next_parts(1);
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].range, range(100, 249));
+        assert_eq!(parts[1].range, range(250, 369));
+        assert!(low_latency.pending_parts.is_empty());
+        assert!(low_latency.preload_hint.is_none());
+    }
+
+    #[test]
+    fn next_parts_listed_hint() {
+        let mut low_latency = LowLatency::new(
+            b"#EXT-X-PART:DURATION=1.0,URI=\"a.m4s\"\n#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"a.m4s\"\n",
+        );
+        assert_eq!(low_latency.next_parts(0).len(), 1);
+
+        let mut low_latency =
+            LowLatency::new(b"#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"b.m4s\",BYTERANGE-START=10\n");
+        assert!(low_latency.next_parts(0).is_empty());
+    }
+
+    #[test]
+    fn blocking_url() {
+        let url = "https://example.com/live.m3u8?token=1"
+            .parse::<Url>()
+            .unwrap();
+        let low_latency = LowLatency::new(PLAYLIST);
+
+        assert_eq!(
+            low_latency.blocking_url(&url, 11, Some(2)).as_str(),
+            "https://example.com/live.m3u8?token=1&_HLS_msn=11&_HLS_part=2"
+        );
+        assert_eq!(
+            low_latency.blocking_url(&url, 12, None).as_str(),
+            "https://example.com/live.m3u8?token=1&_HLS_msn=12"
+        );
+        assert_eq!(
+            LowLatency::default()
+                .blocking_url(&url, 11, Some(2))
+                .as_str(),
+            "https://example.com/live.m3u8?token=1&_HLS_msn=11"
+        );
+    }
+
+    #[test]
+    fn full_playlist() {
+        let url = "https://example.com/live.m3u8?token=1&_HLS_msn=11&_HLS_part=2&_HLS_skip=YES"
+            .parse::<Url>()
+            .unwrap();
+        assert_eq!(
+            full_playlist_url(&url).as_str(),
+            "https://example.com/live.m3u8?token=1"
+        );
+
+        let url = "https://example.com/live.m3u8?_HLS_msn=11"
+            .parse::<Url>()
+            .unwrap();
+        assert_eq!(
+            full_playlist_url(&url).as_str(),
+            "https://example.com/live.m3u8"
+        );
+    }
+}
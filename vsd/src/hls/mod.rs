@@ -1,3 +1,29 @@
+mod define;
+mod low_latency;
 mod playlist;
 
+pub(crate) use define::substitute;
+pub(crate) use low_latency::{LowLatency, full_playlist_url, is_delta};
 pub(crate) use playlist::{parse_as_master, push_segments};
+
+use std::collections::HashMap;
+
+/// Parse an hls attribute list, e.g. `DURATION=0.5,URI="part.mp4"`.
+fn attributes(list: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = list.trim();
+
+    while let Some((name, value)) = rest.split_once('=') {
+        let (value, next) = if let Some(value) = value.strip_prefix('"') {
+            let (value, next) = value.split_once('"').unwrap_or((value, ""));
+            (value, next.trim_start_matches(','))
+        } else {
+            value.split_once(',').unwrap_or((value, ""))
+        };
+
+        attributes.insert(name.trim().to_owned(), value.to_owned());
+        rest = next.trim();
+    }
+
+    attributes
+}
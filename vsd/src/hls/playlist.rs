@@ -1,32 +1,66 @@
+use super::low_latency;
 use crate::{playlist, utils};
+use std::collections::HashMap;
 
 pub(crate) fn parse_as_master(
     playlist: &m3u8_rs::MasterPlaylist,
     base_url: &str,
+    variables: &HashMap<String, String>,
 ) -> playlist::MasterPlaylist {
     let mut streams = Vec::new();
 
     for stream in &playlist.variants {
+        let media_type = variant_media_type(stream);
+
+        // Audio only variants often point to a rendition which is already listed.
+        if media_type == playlist::MediaType::Audio
+            && playlist
+                .alternatives
+                .iter()
+                .any(|x| x.uri.as_ref() == Some(&stream.uri))
+        {
+            continue;
+        }
+
         streams.push(playlist::MediaPlaylist {
+            autoselect: false,
             bandwidth: Some(stream.bandwidth),
             channels: None,
             codecs: stream.codecs.to_owned(),
+            default: false,
+            dolby_vision: other_attribute(stream, "SUPPLEMENTAL-CODECS")
+                .into_iter()
+                .chain(stream.codecs.as_deref())
+                .find_map(playlist::MediaPlaylist::find_dolby_vision),
             extension: Some("ts".to_owned()), // Cannot be comment here
+            forced: false,
             frame_rate: stream.frame_rate.map(|x| x as f32),
+            group_id: None,
+            groups: playlist::Groups {
+                audio: stream.audio.to_owned(),
+                subtitles: stream.subtitles.to_owned(),
+                video: stream.video.to_owned(),
+            },
             id: utils::gen_id(base_url, &stream.uri),
             i_frame: stream.is_i_frame,
             language: None,
             live: false, // Cannot be comment here
+            low_latency: false,
             media_sequence: 0,
-            media_type: playlist::MediaType::Video,
+            media_type,
             playlist_type: playlist::PlaylistType::Hls,
             resolution: if let Some(m3u8_rs::Resolution { width, height }) = stream.resolution {
                 Some((width, height))
             } else {
                 None
             },
+            roles: Vec::new(),
             segments: Vec::new(), // Cannot be comment here
+            start_offset: None,
             uri: stream.uri.to_owned(),
+            variables: variables.clone(),
+            video_range: other_attribute(stream, "VIDEO-RANGE")
+                .and_then(playlist::VideoRange::from_hls),
         });
     }
 
@@ -34,32 +68,49 @@ pub(crate) fn parse_as_master(
         if let Some(uri) = &alternative_stream.uri {
             match alternative_stream.media_type {
                 m3u8_rs::AlternativeMediaType::Video => streams.push(playlist::MediaPlaylist {
+                    autoselect: alternative_stream.autoselect,
                     bandwidth: None, // Cannot be comment here
                     channels: None,
-                    codecs: None,                     // Cannot be comment here
+                    codecs: group_codecs(playlist, alternative_stream, playlist::MediaType::Video),
+                    default: alternative_stream.default,
+                    dolby_vision: None,
                     extension: Some("ts".to_owned()), // Cannot be comment here
-                    frame_rate: None,                 // Cannot be comment here
+                    forced: alternative_stream.forced,
+                    frame_rate: None, // Cannot be comment here
+                    group_id: Some(alternative_stream.group_id.to_owned()),
+                    groups: playlist::Groups::default(),
                     id: utils::gen_id(base_url, uri),
                     i_frame: false, // Cannot be comment here
                     language: None,
                     live: false, // Cannot be comment here
+                    low_latency: false,
                     media_sequence: 0,
                     media_type: playlist::MediaType::Video,
                     playlist_type: playlist::PlaylistType::Hls,
-                    resolution: None,     // Cannot be comment here
+                    resolution: None, // Cannot be comment here
+                    roles: roles(alternative_stream),
                     segments: Vec::new(), // Cannot be comment here
+                    start_offset: None,
                     uri: uri.to_owned(),
+                    variables: variables.clone(),
+                    video_range: None,
                 }),
 
                 m3u8_rs::AlternativeMediaType::Audio => streams.push(playlist::MediaPlaylist {
+                    autoselect: alternative_stream.autoselect,
                     bandwidth: None, // Cannot be comment here
                     channels: alternative_stream
                         .channels
                         .as_ref()
                         .map(|x| x.parse::<f32>().unwrap()),
-                    codecs: None,                     // Cannot be comment here
+                    codecs: group_codecs(playlist, alternative_stream, playlist::MediaType::Audio),
+                    default: alternative_stream.default,
+                    dolby_vision: None,
                     extension: Some("ts".to_owned()), // Cannot be comment here
+                    forced: alternative_stream.forced,
                     frame_rate: None,
+                    group_id: Some(alternative_stream.group_id.to_owned()),
+                    groups: playlist::Groups::default(),
                     id: utils::gen_id(base_url, uri),
                     i_frame: false,
                     language: alternative_stream
@@ -67,22 +118,37 @@ pub(crate) fn parse_as_master(
                         .to_owned()
                         .or(alternative_stream.assoc_language.to_owned()),
                     live: false, // Cannot be comment here
+                    low_latency: false,
                     media_sequence: 0,
                     media_type: playlist::MediaType::Audio,
                     playlist_type: playlist::PlaylistType::Hls,
                     resolution: None,
+                    roles: roles(alternative_stream),
                     segments: Vec::new(), // Cannot be comment here
+                    start_offset: None,
                     uri: uri.to_owned(),
+                    variables: variables.clone(),
+                    video_range: None,
                 }),
 
                 m3u8_rs::AlternativeMediaType::ClosedCaptions
                 | m3u8_rs::AlternativeMediaType::Subtitles => {
                     streams.push(playlist::MediaPlaylist {
+                        autoselect: alternative_stream.autoselect,
                         bandwidth: None,
                         channels: None,
-                        codecs: None,                      // Cannot be comment here
+                        codecs: group_codecs(
+                            playlist,
+                            alternative_stream,
+                            playlist::MediaType::Subtitles,
+                        ),
+                        default: alternative_stream.default,
+                        dolby_vision: None,
                         extension: Some("vtt".to_owned()), // Cannot be comment here
+                        forced: alternative_stream.forced,
                         frame_rate: None,
+                        group_id: Some(alternative_stream.group_id.to_owned()),
+                        groups: playlist::Groups::default(),
                         id: utils::gen_id(base_url, uri),
                         i_frame: false,
                         language: alternative_stream
@@ -90,24 +156,35 @@ pub(crate) fn parse_as_master(
                             .to_owned()
                             .or(alternative_stream.assoc_language.to_owned()),
                         live: false, // Cannot be comment here
+                        low_latency: false,
                         media_sequence: 0,
                         media_type: playlist::MediaType::Subtitles,
                         playlist_type: playlist::PlaylistType::Hls,
                         resolution: None,
+                        roles: roles(alternative_stream),
                         segments: Vec::new(), // Cannot be comment here
+                        start_offset: None,
                         uri: uri.to_owned(),
+                        variables: variables.clone(),
+                        video_range: None,
                     })
                 }
 
                 m3u8_rs::AlternativeMediaType::Other(_) => streams.push(playlist::MediaPlaylist {
+                    autoselect: alternative_stream.autoselect,
                     bandwidth: None,
                     channels: alternative_stream
                         .channels
                         .as_ref()
                         .map(|x| x.parse::<f32>().unwrap()),
-                    codecs: None,     // Cannot be comment here
-                    extension: None,  // Cannot be comment here
+                    codecs: None, // Cannot be comment here
+                    default: alternative_stream.default,
+                    dolby_vision: None,
+                    extension: None, // Cannot be comment here
+                    forced: alternative_stream.forced,
                     frame_rate: None, // Cannot be comment here
+                    group_id: Some(alternative_stream.group_id.to_owned()),
+                    groups: playlist::Groups::default(),
                     id: utils::gen_id(base_url, uri),
                     i_frame: false, // Cannot be comment here
                     language: alternative_stream
@@ -115,24 +192,122 @@ pub(crate) fn parse_as_master(
                         .to_owned()
                         .or(alternative_stream.assoc_language.to_owned()),
                     live: false, // Cannot be comment here
+                    low_latency: false,
                     media_sequence: 0,
                     media_type: playlist::MediaType::Undefined,
                     playlist_type: playlist::PlaylistType::Hls,
-                    resolution: None,     // Cannot be comment here
+                    resolution: None, // Cannot be comment here
+                    roles: roles(alternative_stream),
                     segments: Vec::new(), // Cannot be comment here
+                    start_offset: None,
                     uri: uri.to_owned(),
+                    variables: variables.clone(),
+                    video_range: None,
                 }),
             }
         }
     }
 
     playlist::MasterPlaylist {
+        duration: None, // Known only after fetching a media playlist
         playlist_type: playlist::PlaylistType::Hls,
         uri: base_url.to_owned(),
         streams,
     }
 }
 
+/// Roles from the `CHARACTERISTICS` of a rendition, named after their dash counterparts.
+fn roles(alternative_stream: &m3u8_rs::AlternativeMedia) -> Vec<String> {
+    let mut roles = Vec::new();
+
+    for characteristic in alternative_stream
+        .characteristics
+        .iter()
+        .flat_map(|x| x.split(','))
+    {
+        let role = match characteristic.trim() {
+            "public.accessibility.describes-music-and-sound" => "sdh",
+            "public.accessibility.describes-video" => "description",
+            "public.accessibility.transcribes-spoken-dialog" => "caption",
+            "public.auxiliary-content" => "supplementary",
+            "public.easy-to-read" => "easyreader",
+            _ => continue,
+        };
+
+        if !roles.iter().any(|x| x == role) {
+            roles.push(role.to_owned());
+        }
+    }
+
+    roles
+}
+
+/// Variant attribute which m3u8-rs does not parse, e.g. `VIDEO-RANGE`.
+fn other_attribute<'a>(stream: &'a m3u8_rs::VariantStream, name: &str) -> Option<&'a str> {
+    stream
+        .other_attributes
+        .as_ref()
+        .and_then(|x| x.get(name))
+        .map(|x| x.as_str())
+}
+
+/// Classify a variant by its CODECS attribute. Variants without any video codec or resolution
+/// are audio only.
+fn variant_media_type(stream: &m3u8_rs::VariantStream) -> playlist::MediaType {
+    let types = stream
+        .codecs
+        .iter()
+        .flat_map(|x| x.split(','))
+        .filter_map(playlist::MediaType::from_codec)
+        .collect::<Vec<_>>();
+
+    if stream.resolution.is_none()
+        && !types.contains(&playlist::MediaType::Video)
+        && types.contains(&playlist::MediaType::Audio)
+    {
+        playlist::MediaType::Audio
+    } else {
+        playlist::MediaType::Video
+    }
+}
+
+/// Codecs of a rendition, taken from the variants which reference its group.
+fn group_codecs(
+    playlist: &m3u8_rs::MasterPlaylist,
+    alternative_stream: &m3u8_rs::AlternativeMedia,
+    media_type: playlist::MediaType,
+) -> Option<String> {
+    let mut codecs = Vec::new();
+
+    for stream in &playlist.variants {
+        let group_id = match media_type {
+            playlist::MediaType::Video => &stream.video,
+            playlist::MediaType::Audio => &stream.audio,
+            playlist::MediaType::Subtitles => &stream.subtitles,
+            playlist::MediaType::Undefined => return None,
+        };
+
+        if group_id.as_ref() != Some(&alternative_stream.group_id) {
+            continue;
+        }
+
+        for codec in stream
+            .codecs
+            .iter()
+            .flat_map(|x| x.split(','))
+            .map(str::trim)
+        {
+            if playlist::MediaType::from_codec(codec).as_ref() == Some(&media_type)
+                && !codecs.contains(&codec)
+            {
+                codecs.push(codec);
+            }
+        }
+    }
+
+    (!codecs.is_empty()).then(|| codecs.join(","))
+}
+
 pub(crate) fn push_segments(
     playlist: &m3u8_rs::MediaPlaylist,
     stream: &mut playlist::MediaPlaylist,
@@ -140,47 +315,27 @@ pub(crate) fn push_segments(
     stream.i_frame = playlist.i_frames_only;
     stream.live = !playlist.end_list;
     stream.media_sequence = playlist.media_sequence;
+    stream.start_offset = playlist.start.as_ref().map(|x| x.time_offset as f32);
 
     let mut previous_byterange_end = 0;
+    let mut previous_part_end = 0;
+    let mut start = 0.0;
 
     for segment in &playlist.segments {
         let map = segment.map.as_ref().map(|x| playlist::Map {
             uri: x.uri.to_owned(),
-            range: x.byte_range.as_ref().map(|x| {
-                let offset = x.offset.unwrap_or(0);
-
-                let (start, end) = if offset == 0 {
-                    (
-                        previous_byterange_end,
-                        (previous_byterange_end + x.length) - 1,
-                    )
-                } else {
-                    (x.length, (x.length + offset) - 1)
-                };
-
-                previous_byterange_end = end;
-                playlist::Range { start, end }
-            }),
+            // Init maps don't continue the segment byte ranges.
+            range: x.byte_range.as_ref().map(|x| byte_range(x, &mut 0)),
         });
 
-        let range = segment.byte_range.as_ref().map(|x| {
-            let offset = x.offset.unwrap_or(0);
-
-            let (start, end) = if offset == 0 {
-                (
-                    previous_byterange_end,
-                    (previous_byterange_end + x.length) - 1,
-                )
-            } else {
-                (x.length, (x.length + offset) - 1)
-            };
-
-            previous_byterange_end = end;
-            playlist::Range { start, end }
-        });
+        let range = segment
+            .byte_range
+            .as_ref()
+            .map(|x| byte_range(x, &mut previous_byterange_end));
 
         stream.segments.push(playlist::Segment {
             duration: segment.duration,
+            gap: segment.unknown_tags.iter().any(|x| x.tag == "X-GAP"),
             key: if let Some(m3u8_rs::Key {
                 iv,
                 keyformat,
@@ -232,9 +387,29 @@ pub(crate) fn push_segments(
                 None
             },
             map,
+            parts: segment
+                .unknown_tags
+                .iter()
+                .filter(|x| x.tag == "X-PART")
+                .filter_map(|x| {
+                    low_latency::part(x.rest.as_deref().unwrap_or(""), &mut previous_part_end)
+                })
+                .collect(),
             range,
+            start: Some(start),
             uri: segment.uri.to_owned(),
         });
+
+        start += segment.duration as f64;
+
+        // Playlist level tags are attached to the first segment by m3u8_rs.
+        if segment
+            .unknown_tags
+            .iter()
+            .any(|x| x.tag == "X-PART-INF" || x.tag == "X-SERVER-CONTROL")
+        {
+            stream.low_latency = true;
+        }
     }
 
     if let Some(segment) = stream.segments.first() {
@@ -251,3 +426,14 @@ pub(crate) fn push_segments(
         }
     }
 }
+
+/// Resolve an `n[@o]` byte range. Without an offset, the range starts where the previous one ended.
+fn byte_range(range: &m3u8_rs::ByteRange, previous_end: &mut u64) -> playlist::Range {
+    let start = range.offset.unwrap_or(*previous_end);
+    *previous_end = start + range.length;
+
+    playlist::Range {
+        end: start + range.length - 1,
+        start,
+    }
+}
@@ -0,0 +1,357 @@
+/*
+    REFERENCES
+    ----------
+
+    1. https://www.rfc-editor.org/rfc/rfc5646
+    2. https://www.loc.gov/standards/iso639-2/php/code_list.php
+    3. https://iso639-3.sil.org/code_tables/macrolanguage_mappings/data
+
+*/
+
+/// Language tag normalized from BCP-47, ISO 639-1, ISO 639-2 (B/T) or ISO 639-3 codes.
+#[derive(Debug)]
+pub struct Language {
+    /// ISO 639-1 code, or the three letter code when the language has none.
+    pub code: String,
+    pub region: Option<String>,
+    pub script: Option<String>,
+}
+
+impl Language {
+    /// `(ISO 639-1, ISO 639-2/B, ISO 639-2/T)` codes.
+    const CODES: &[(&str, &str, &str)] = &[
+        ("aa", "aar", "aar"),
+        ("ab", "abk", "abk"),
+        ("ae", "ave", "ave"),
+        ("af", "afr", "afr"),
+        ("ak", "aka", "aka"),
+        ("am", "amh", "amh"),
+        ("an", "arg", "arg"),
+        ("ar", "ara", "ara"),
+        ("as", "asm", "asm"),
+        ("av", "ava", "ava"),
+        ("ay", "aym", "aym"),
+        ("az", "aze", "aze"),
+        ("ba", "bak", "bak"),
+        ("be", "bel", "bel"),
+        ("bg", "bul", "bul"),
+        ("bi", "bis", "bis"),
+        ("bm", "bam", "bam"),
+        ("bn", "ben", "ben"),
+        ("bo", "tib", "bod"),
+        ("br", "bre", "bre"),
+        ("bs", "bos", "bos"),
+        ("ca", "cat", "cat"),
+        ("ce", "che", "che"),
+        ("ch", "cha", "cha"),
+        ("co", "cos", "cos"),
+        ("cr", "cre", "cre"),
+        ("cs", "cze", "ces"),
+        ("cu", "chu", "chu"),
+        ("cv", "chv", "chv"),
+        ("cy", "wel", "cym"),
+        ("da", "dan", "dan"),
+        ("de", "ger", "deu"),
+        ("dv", "div", "div"),
+        ("dz", "dzo", "dzo"),
+        ("ee", "ewe", "ewe"),
+        ("el", "gre", "ell"),
+        ("en", "eng", "eng"),
+        ("eo", "epo", "epo"),
+        ("es", "spa", "spa"),
+        ("et", "est", "est"),
+        ("eu", "baq", "eus"),
+        ("fa", "per", "fas"),
+        ("ff", "ful", "ful"),
+        ("fi", "fin", "fin"),
+        ("fj", "fij", "fij"),
+        ("fo", "fao", "fao"),
+        ("fr", "fre", "fra"),
+        ("fy", "fry", "fry"),
+        ("ga", "gle", "gle"),
+        ("gd", "gla", "gla"),
+        ("gl", "glg", "glg"),
+        ("gn", "grn", "grn"),
+        ("gu", "guj", "guj"),
+        ("gv", "glv", "glv"),
+        ("ha", "hau", "hau"),
+        ("he", "heb", "heb"),
+        ("hi", "hin", "hin"),
+        ("ho", "hmo", "hmo"),
+        ("hr", "hrv", "hrv"),
+        ("ht", "hat", "hat"),
+        ("hu", "hun", "hun"),
+        ("hy", "arm", "hye"),
+        ("hz", "her", "her"),
+        ("ia", "ina", "ina"),
+        ("id", "ind", "ind"),
+        ("ie", "ile", "ile"),
+        ("ig", "ibo", "ibo"),
+        ("ii", "iii", "iii"),
+        ("ik", "ipk", "ipk"),
+        ("io", "ido", "ido"),
+        ("is", "ice", "isl"),
+        ("it", "ita", "ita"),
+        ("iu", "iku", "iku"),
+        ("ja", "jpn", "jpn"),
+        ("jv", "jav", "jav"),
+        ("ka", "geo", "kat"),
+        ("kg", "kon", "kon"),
+        ("ki", "kik", "kik"),
+        ("kj", "kua", "kua"),
+        ("kk", "kaz", "kaz"),
+        ("kl", "kal", "kal"),
+        ("km", "khm", "khm"),
+        ("kn", "kan", "kan"),
+        ("ko", "kor", "kor"),
+        ("kr", "kau", "kau"),
+        ("ks", "kas", "kas"),
+        ("ku", "kur", "kur"),
+        ("kv", "kom", "kom"),
+        ("kw", "cor", "cor"),
+        ("ky", "kir", "kir"),
+        ("la", "lat", "lat"),
+        ("lb", "ltz", "ltz"),
+        ("lg", "lug", "lug"),
+        ("li", "lim", "lim"),
+        ("ln", "lin", "lin"),
+        ("lo", "lao", "lao"),
+        ("lt", "lit", "lit"),
+        ("lu", "lub", "lub"),
+        ("lv", "lav", "lav"),
+        ("mg", "mlg", "mlg"),
+        ("mh", "mah", "mah"),
+        ("mi", "mao", "mri"),
+        ("mk", "mac", "mkd"),
+        ("ml", "mal", "mal"),
+        ("mn", "mon", "mon"),
+        ("mr", "mar", "mar"),
+        ("ms", "may", "msa"),
+        ("mt", "mlt", "mlt"),
+        ("my", "bur", "mya"),
+        ("na", "nau", "nau"),
+        ("nb", "nob", "nob"),
+        ("nd", "nde", "nde"),
+        ("ne", "nep", "nep"),
+        ("ng", "ndo", "ndo"),
+        ("nl", "dut", "nld"),
+        ("nn", "nno", "nno"),
+        ("no", "nor", "nor"),
+        ("nr", "nbl", "nbl"),
+        ("nv", "nav", "nav"),
+        ("ny", "nya", "nya"),
+        ("oc", "oci", "oci"),
+        ("oj", "oji", "oji"),
+        ("om", "orm", "orm"),
+        ("or", "ori", "ori"),
+        ("os", "oss", "oss"),
+        ("pa", "pan", "pan"),
+        ("pi", "pli", "pli"),
+        ("pl", "pol", "pol"),
+        ("ps", "pus", "pus"),
+        ("pt", "por", "por"),
+        ("qu", "que", "que"),
+        ("rm", "roh", "roh"),
+        ("rn", "run", "run"),
+        ("ro", "rum", "ron"),
+        ("ru", "rus", "rus"),
+        ("rw", "kin", "kin"),
+        ("sa", "san", "san"),
+        ("sc", "srd", "srd"),
+        ("sd", "snd", "snd"),
+        ("se", "sme", "sme"),
+        ("sg", "sag", "sag"),
+        ("si", "sin", "sin"),
+        ("sk", "slo", "slk"),
+        ("sl", "slv", "slv"),
+        ("sm", "smo", "smo"),
+        ("sn", "sna", "sna"),
+        ("so", "som", "som"),
+        ("sq", "alb", "sqi"),
+        ("sr", "srp", "srp"),
+        ("ss", "ssw", "ssw"),
+        ("st", "sot", "sot"),
+        ("su", "sun", "sun"),
+        ("sv", "swe", "swe"),
+        ("sw", "swa", "swa"),
+        ("ta", "tam", "tam"),
+        ("te", "tel", "tel"),
+        ("tg", "tgk", "tgk"),
+        ("th", "tha", "tha"),
+        ("ti", "tir", "tir"),
+        ("tk", "tuk", "tuk"),
+        ("tl", "tgl", "tgl"),
+        ("tn", "tsn", "tsn"),
+        ("to", "ton", "ton"),
+        ("tr", "tur", "tur"),
+        ("ts", "tso", "tso"),
+        ("tt", "tat", "tat"),
+        ("tw", "twi", "twi"),
+        ("ty", "tah", "tah"),
+        ("ug", "uig", "uig"),
+        ("uk", "ukr", "ukr"),
+        ("ur", "urd", "urd"),
+        ("uz", "uzb", "uzb"),
+        ("ve", "ven", "ven"),
+        ("vi", "vie", "vie"),
+        ("vo", "vol", "vol"),
+        ("wa", "wln", "wln"),
+        ("wo", "wol", "wol"),
+        ("xh", "xho", "xho"),
+        ("yi", "yid", "yid"),
+        ("yo", "yor", "yor"),
+        ("za", "zha", "zha"),
+        ("zh", "chi", "zho"),
+        ("zu", "zul", "zul"),
+    ];
+
+    /// Deprecated ISO 639-1 codes and ISO 639-3 individual languages which are usually tagged
+    /// with their macrolanguage.
+    const ALIASES: &[(&str, &str)] = &[
+        ("arb", "ar"),
+        ("cmn", "zh"),
+        ("ekk", "et"),
+        ("in", "id"),
+        ("iw", "he"),
+        ("ji", "yi"),
+        ("jw", "jv"),
+        ("lvs", "lv"),
+        ("mo", "ro"),
+        ("pes", "fa"),
+        ("swh", "sw"),
+        ("zsm", "ms"),
+    ];
+
+    /// Parse a language tag like `ger`, `pt-BR`, `zh-Hant-TW` or `en_US`. Extension and private
+    /// use subtags are ignored.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let mut subtags = tag.trim().split(['-', '_']);
+        let primary = subtags.next()?.to_ascii_lowercase();
+
+        if !(2..=3).contains(&primary.len()) || !primary.chars().all(|x| x.is_ascii_alphabetic()) {
+            return None;
+        }
+
+        let code = Self::ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == primary)
+            .map(|(_, code)| code.to_string())
+            .or_else(|| {
+                Self::CODES
+                    .iter()
+                    .find(|(_, b, t)| *b == primary || *t == primary)
+                    .map(|(code, _, _)| code.to_string())
+            })
+            .unwrap_or(primary);
+
+        let mut region = None;
+        let mut script = None;
+
+        for subtag in subtags {
+            match subtag.len() {
+                1 => break,
+                2 if subtag.chars().all(|x| x.is_ascii_alphabetic()) => {
+                    region = Some(subtag.to_ascii_uppercase());
+                }
+                3 if subtag.chars().all(|x| x.is_ascii_digit()) => {
+                    region = Some(subtag.to_owned());
+                }
+                4 if script.is_none() && region.is_none() => {
+                    script =
+                        Some(subtag[..1].to_ascii_uppercase() + &subtag[1..].to_ascii_lowercase());
+                }
+                _ => (),
+            }
+        }
+
+        // Chinese regions imply a script, so that zh-TW can match zh-Hant.
+        if script.is_none() && code == "zh" {
+            script = match region.as_deref() {
+                Some("HK" | "MO" | "TW") => Some("Hant".to_owned()),
+                Some("CN" | "SG") => Some("Hans".to_owned()),
+                _ => None,
+            };
+        }
+
+        Some(Self {
+            code,
+            region,
+            script,
+        })
+    }
+
+    /// ISO 639-2/B code, as used by matroska and ffmpeg `language=` metadata. Unknown two
+    /// letter codes are not valid there, so they become `und`.
+    pub fn iso639_2(&self) -> String {
+        Self::CODES
+            .iter()
+            .find(|(code, _, _)| *code == self.code)
+            .map(|(_, b, _)| b.to_string())
+            .unwrap_or_else(|| match self.code.len() {
+                2 => "und".to_owned(),
+                _ => self.code.to_owned(),
+            })
+    }
+
+    /// How far a stream language is from this preferred language. `0` is an exact match, `1`
+    /// differs only in region and `2` also differs in script, `None` is a different language.
+    pub fn distance(&self, other: &Self) -> Option<u8> {
+        if self.code != other.code {
+            return None;
+        }
+
+        if self.script.is_some() && other.script.is_some() && self.script != other.script {
+            return Some(2);
+        }
+
+        if self.script != other.script || self.region != other.region {
+            return Some(1);
+        }
+
+        Some(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(tag: &str) -> Language {
+        Language::parse(tag).unwrap()
+    }
+
+    #[test]
+    fn codes() {
+        assert_eq!(parse("ger").code, "de");
+        assert_eq!(parse("deu").code, "de");
+        assert_eq!(parse("de").iso639_2(), "ger");
+        assert_eq!(parse("iw").code, "he");
+        assert_eq!(parse("fil").code, "fil");
+        assert_eq!(parse("fil").iso639_2(), "fil");
+        assert_eq!(parse("qq").iso639_2(), "und");
+        assert!(Language::parse("english").is_none());
+    }
+
+    #[test]
+    fn subtags() {
+        let lang = parse("zh-Hant-TW");
+        assert_eq!(lang.script.as_deref(), Some("Hant"));
+        assert_eq!(lang.region.as_deref(), Some("TW"));
+
+        assert_eq!(parse("zh-TW").script.as_deref(), Some("Hant"));
+        assert_eq!(parse("zh_cn").script.as_deref(), Some("Hans"));
+        assert_eq!(parse("es-419").region.as_deref(), Some("419"));
+        assert_eq!(parse("en-US-x-private").region.as_deref(), Some("US"));
+    }
+
+    #[test]
+    fn distance() {
+        assert_eq!(parse("fi").distance(&parse("fil")), None);
+        assert_eq!(parse("ger").distance(&parse("de")), Some(0));
+        assert_eq!(parse("pt-BR").distance(&parse("pt-BR")), Some(0));
+        assert_eq!(parse("pt-BR").distance(&parse("pt-PT")), Some(1));
+        assert_eq!(parse("pt").distance(&parse("pt-PT")), Some(1));
+        assert_eq!(parse("zh-Hant").distance(&parse("zh-TW")), Some(1));
+        assert_eq!(parse("zh-Hant").distance(&parse("zh-CN")), Some(2));
+    }
+}
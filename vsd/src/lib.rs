@@ -3,10 +3,13 @@ mod cookie;
 mod dash;
 mod downloader;
 mod hls;
+mod language;
 mod logger;
 mod options;
+mod picker;
 mod playlist;
 mod progress;
+mod request;
 mod selector;
 mod utils;
 
@@ -1,5 +1,9 @@
 use colored::{ColoredString, Colorize};
 use log::{Level, LevelFilter, Metadata, Record};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Write logs to stderr instead of stdout, used when stdout carries media data.
+pub static STDERR: AtomicBool = AtomicBool::new(false);
 
 pub struct Logger;
 
@@ -15,10 +19,10 @@ impl log::Log for Logger {
                 LevelFilter::Error | LevelFilter::Warn | LevelFilter::Info => {
                     match record.level() {
                         Level::Info => {
-                            println!("{}", record.args());
+                            write(format_args!("{}", record.args()));
                         }
                         _ => {
-                            println!("{} {}", label(record.level()), record.args());
+                            write(format_args!("{} {}", label(record.level()), record.args()));
                         }
                     }
                 }
@@ -28,13 +32,13 @@ impl log::Log for Logger {
                         _ => "[unk]".dimmed(),
                     };
 
-                    println!(
+                    write(format_args!(
                         "{} {} {} {}",
                         label(record.level()),
                         record.target().dimmed(),
                         location,
                         record.args()
-                    );
+                    ));
                 }
             }
         }
@@ -43,6 +47,14 @@ impl log::Log for Logger {
     fn flush(&self) {}
 }
 
+fn write(args: std::fmt::Arguments) {
+    if STDERR.load(Ordering::SeqCst) {
+        eprintln!("{}", args);
+    } else {
+        println!("{}", args);
+    }
+}
+
 fn label(level: Level) -> ColoredString {
     match level {
         Level::Debug => "[DEBUG]".bold().blue(),
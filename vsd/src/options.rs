@@ -1,4 +1,5 @@
-use std::collections::HashSet;
+use crate::{language::Language, playlist::MediaPlaylist};
+use std::{collections::HashSet, path::PathBuf};
 
 pub enum Interaction {
     Modern,
@@ -6,11 +7,21 @@ pub enum Interaction {
     Raw,
 }
 
+/// Muxing settings of a download, previewed by the interactive picker.
+#[derive(Clone, Debug, Default)]
+pub struct MuxOptions {
+    pub fill_gaps: bool,
+    pub output: Option<PathBuf>,
+    pub subs_codec: String,
+}
+
 #[derive(Debug, Default)]
 pub struct SelectOptions {
     pub vid: Preferences,
     pub aud: Preferences,
     pub sub: Preferences,
+    /// Stable stream ids (`MediaPlaylist::id`), resolved into `stream_indices` while selecting.
+    pub stream_ids: HashSet<String>,
     pub stream_indices: HashSet<usize>,
     pub strict_indices: bool,
 }
@@ -19,11 +30,28 @@ pub struct SelectOptions {
 pub struct Preferences {
     pub all: bool,
     pub skip: bool,
+    pub filters: Vec<Filter>,
     pub languages: HashSet<String>,
-    pub resolutions: HashSet<(u16, u16)>,
+    /// Select at most this many streams (`top2`).
+    pub limit: Option<usize>,
+    /// Select one stream for every language (`best-per-lang`).
+    pub per_language: bool,
+    pub resolutions: Vec<(String, Resolution)>,
     pub quality: Quality,
 }
 
+/// Video resolution query, e.g. `1280x720`, `1080p`, `<=1080p` or `~720p`.
+#[derive(Debug)]
+pub enum Resolution {
+    /// Streams whose height or width fits the class, so `1080p` also matches 1920x800.
+    Class(u64, u64),
+    Exact(u64, u64),
+    /// Best stream at or below a height.
+    Max(u64),
+    /// Stream with the closest height.
+    Nearest(u64),
+}
+
 #[derive(Debug, Default)]
 pub enum Quality {
     Best,
@@ -32,6 +60,36 @@ pub enum Quality {
     Worst,
 }
 
+/// Attribute predicate, e.g. `codec:hvc1`, `bw<=5M`, `hdr` or `!description`.
+#[derive(Debug)]
+pub enum Filter {
+    Codec(String),
+    Compare(Field, Op, f64),
+    DolbyVision,
+    Hdr,
+    /// Role which the stream must have, or must not have when negated (`!`).
+    Role(String, bool),
+    Sdr,
+}
+
+#[derive(Debug)]
+pub enum Field {
+    Bandwidth,
+    Channels,
+    FrameRate,
+    Height,
+    Width,
+}
+
+#[derive(Debug)]
+pub enum Op {
+    Eq,
+    Ge,
+    Gt,
+    Le,
+    Lt,
+}
+
 impl std::str::FromStr for SelectOptions {
     type Err = std::convert::Infallible;
 
@@ -62,17 +120,49 @@ impl std::str::FromStr for SelectOptions {
         }
 
         // Complex format: "v=best:a=en:s=skip"
-        for stream in s.split_terminator(':') {
+        // Predicates like "codec:hvc1" also contain ':', so a new section only starts at "x=".
+        let mut sections = Vec::<String>::new();
+
+        for part in s.split_terminator(':') {
+            match sections.last_mut() {
+                Some(section) if !Self::is_section(part) => {
+                    section.push(':');
+                    section.push_str(part);
+                }
+                _ => sections.push(part.to_owned()),
+            }
+        }
+
+        for stream in &sections {
             let Some((code, queries)) = stream.split_once('=') else {
                 continue;
             };
 
+            if code.trim() == "id" {
+                opts.stream_ids.extend(
+                    queries
+                        .split_terminator(',')
+                        .map(|x| x.trim().to_ascii_lowercase()),
+                );
+                continue;
+            }
+
             for query in queries.split_terminator(',').map(|x| x.trim()) {
                 if let Some(idx) = query.parse::<usize>().ok().and_then(|x| x.checked_sub(1)) {
                     opts.stream_indices.insert(idx);
                     continue;
                 }
 
+                if let Some(filter) = Filter::parse(query) {
+                    match code {
+                        "v" => opts.vid.filters.push(filter),
+                        "a" => opts.aud.filters.push(filter),
+                        "s" => opts.sub.filters.push(filter),
+                        _ => (),
+                    }
+                    continue;
+                }
+
                 match code {
                     "v" => Self::parse_vid_query(query, &mut opts.vid),
                     "a" => Self::parse_lang_query(query, &mut opts.aud),
@@ -82,26 +172,25 @@ impl std::str::FromStr for SelectOptions {
             }
         }
 
+        // Only ids: "id=ab12cd3,ef45678"
+        if !sections.is_empty()
+            && sections.iter().all(|x| {
+                x.split_once('=')
+                    .is_some_and(|(code, _)| code.trim() == "id")
+            })
+        {
+            opts.strict_indices = true;
+        }
+
         Ok(opts)
     }
 }
 
 impl SelectOptions {
-    const RESOLUTIONS: &[(&str, (u16, u16))] = &[
-        ("144p", (256, 144)),
-        ("240p", (426, 240)),
-        ("360p", (640, 360)),
-        ("480p", (854, 480)),
-        ("720p", (1280, 720)),
-        ("hd", (1280, 720)),
-        ("1080p", (1920, 1080)),
-        ("fhd", (1920, 1080)),
-        ("2k", (2048, 1080)),
-        ("1440p", (2560, 1440)),
-        ("qhd", (2560, 1440)),
-        ("4k", (3840, 2160)),
-        ("8k", (7680, 4320)),
-    ];
+    fn is_section(part: &str) -> bool {
+        part.split_once('=')
+            .is_some_and(|(code, _)| matches!(code.trim(), "a" | "id" | "s" | "v"))
+    }
 
     fn parse_vid_query(query: &str, prefs: &mut Preferences) {
         match query {
@@ -109,16 +198,9 @@ impl SelectOptions {
             "skip" => prefs.skip = true,
             "best" | "high" | "max" => prefs.quality = Quality::Best,
             "low" | "min" | "worst" => prefs.quality = Quality::Worst,
-            q if q.contains('x') => {
-                if let Some((w, h)) = q.split_once('x')
-                    && let (Ok(w), Ok(h)) = (w.parse(), h.parse())
-                {
-                    prefs.resolutions.insert((w, h));
-                }
-            }
             q => {
-                if let Some(&(_, res)) = Self::RESOLUTIONS.iter().find(|(name, _)| *name == q) {
-                    prefs.resolutions.insert(res);
+                if let Some(resolution) = Resolution::parse(q) {
+                    prefs.resolutions.push((q.to_owned(), resolution));
                 }
             }
         }
@@ -130,6 +212,23 @@ impl SelectOptions {
             "skip" => prefs.skip = true,
             "best" | "high" | "max" => prefs.quality = Quality::Best,
             "low" | "min" | "worst" => prefs.quality = Quality::Worst,
+            "best-per-lang" | "best-per-language" => {
+                prefs.per_language = true;
+                prefs.quality = Quality::Best;
+            }
+            q if q
+                .strip_prefix("top")
+                .is_some_and(|x| x.parse::<usize>().is_ok()) =>
+            {
+                prefs.limit = q[3..].parse().ok();
+            }
+            // "de:best" applies the quality to the whole language list, e.g. "a=en,de:best".
+            q if q.contains(':') => {
+                if let Some((lang, quality)) = q.split_once(':') {
+                    Self::parse_lang_query(lang, prefs);
+                    Self::parse_lang_query(quality, prefs);
+                }
+            }
             lang => {
                 prefs.languages.insert(lang.to_owned());
             }
@@ -138,28 +237,353 @@ impl SelectOptions {
 }
 
 impl Preferences {
-    pub fn contains_exact_lang(&mut self, lang: &str) -> bool {
-        if self.languages.contains(lang) {
-            self.languages.remove(lang);
-            return true;
-        }
-        false
+    /// Whether a stream satisfies every attribute predicate.
+    pub fn matches(&self, stream: &MediaPlaylist) -> bool {
+        self.filters.iter().all(|x| x.matches(stream))
     }
 
-    pub fn contains_siml_lang(&mut self, lang: &str) -> bool {
-        let code = lang.to_lowercase();
-        let code = code.get(0..2);
+    /// Take a preferred language which is `distance` away from the stream language (see
+    /// [`Language::distance`]). Tags which can't be parsed only match themselves.
+    pub fn take_language(&mut self, lang: &str, distance: u8) -> bool {
+        let stream = Language::parse(lang);
 
-        let lang = self
+        let preferred = self
             .languages
             .iter()
-            .find(|x| x.to_lowercase().get(0..2) == code)
+            .find(|x| match (Language::parse(x), &stream) {
+                (Some(x), Some(stream)) => x.distance(stream) == Some(distance),
+                _ => distance == 0 && x.eq_ignore_ascii_case(lang),
+            })
             .cloned();
 
-        if let Some(lang) = lang {
-            self.languages.remove(&lang);
+        if let Some(preferred) = preferred {
+            self.languages.remove(&preferred);
             return true;
         }
         false
     }
 }
+
+impl Resolution {
+    const RESOLUTIONS: &[(&str, (u64, u64))] = &[
+        ("144p", (256, 144)),
+        ("240p", (426, 240)),
+        ("360p", (640, 360)),
+        ("480p", (854, 480)),
+        ("720p", (1280, 720)),
+        ("hd", (1280, 720)),
+        ("1080p", (1920, 1080)),
+        ("fhd", (1920, 1080)),
+        ("2k", (2048, 1080)),
+        ("1440p", (2560, 1440)),
+        ("qhd", (2560, 1440)),
+        ("4k", (3840, 2160)),
+        ("8k", (7680, 4320)),
+    ];
+
+    fn parse(query: &str) -> Option<Self> {
+        if let Some(height) = query.strip_prefix("<=") {
+            return Self::height(height).map(Self::Max);
+        }
+
+        if let Some(height) = query.strip_prefix('~') {
+            return Self::height(height).map(Self::Nearest);
+        }
+
+        if let Some((w, h)) = query.split_once('x')
+            && let (Ok(w), Ok(h)) = (w.parse(), h.parse())
+        {
+            return Some(Self::Exact(w, h));
+        }
+
+        Self::RESOLUTIONS
+            .iter()
+            .find(|(name, _)| *name == query)
+            .map(|&(_, (w, h))| Self::Class(w, h))
+    }
+
+    /// Height of a resolution name like `fhd`, or a number with an optional `p`.
+    fn height(query: &str) -> Option<u64> {
+        Self::RESOLUTIONS
+            .iter()
+            .find(|(name, _)| *name == query)
+            .map(|&(_, (w, h))| Self::class_height(w, h))
+            .or_else(|| query.strip_suffix('p').unwrap_or(query).parse().ok())
+    }
+
+    /// Height of the 16:9 class a resolution belongs to, 1920x800 and 1440x1080 are both 1080.
+    pub fn class_height(w: u64, h: u64) -> u64 {
+        let (w, h) = if h > w { (h, w) } else { (w, h) };
+        h.max(w * 9 / 16)
+    }
+
+    pub fn matches(&self, (w, h): (u64, u64)) -> bool {
+        match self {
+            Self::Class(cw, ch) => w == *cw || h == *ch,
+            Self::Exact(ew, eh) => w == *ew && h == *eh,
+            Self::Max(height) => Self::class_height(w, h) <= *height,
+            Self::Nearest(_) => true,
+        }
+    }
+
+    /// Target height used to find the closest stream.
+    pub fn target(&self) -> u64 {
+        match self {
+            Self::Class(w, h) | Self::Exact(w, h) => Self::class_height(*w, *h),
+            Self::Max(height) | Self::Nearest(height) => *height,
+        }
+    }
+}
+
+impl Filter {
+    const CODEC_ALIASES: &[(&str, &[&str])] = &[
+        ("aac", &["mp4a"]),
+        ("ac3", &["ac-3"]),
+        ("av1", &["av01"]),
+        ("avc", &["avc1", "avc3"]),
+        ("dv", &["dva1", "dvav", "dvh1", "dvhe"]),
+        ("eac3", &["ec-3"]),
+        ("h264", &["avc1", "avc3"]),
+        ("h265", &["hvc1", "hev1"]),
+        ("hevc", &["hvc1", "hev1"]),
+        ("vp9", &["vp09"]),
+    ];
+
+    const ROLES: &[&str] = &[
+        "alternate",
+        "caption",
+        "commentary",
+        "default",
+        "description",
+        "dub",
+        "easyreader",
+        "emergency",
+        "enhanced-audio-intelligibility",
+        "forced",
+        "main",
+        "sdh",
+        "sign",
+        "subtitle",
+        "supplementary",
+    ];
+
+    fn parse(query: &str) -> Option<Self> {
+        let (role, negate) = match query.strip_prefix('!') {
+            Some(role) => (role, true),
+            None => (query, false),
+        };
+
+        if Self::ROLES.contains(&role) {
+            return Some(Self::Role(role.to_owned(), negate));
+        }
+
+        match query {
+            "dolby-vision" | "dovi" | "dv" => return Some(Self::DolbyVision),
+            "hdr" => return Some(Self::Hdr),
+            "sdr" => return Some(Self::Sdr),
+            _ => (),
+        }
+
+        if let Some(codec) = query.strip_prefix("codec:") {
+            return Some(Self::Codec(codec.trim().to_ascii_lowercase()));
+        }
+
+        let position = query.find(['<', '>', '='])?;
+        let (name, rest) = query.split_at(position);
+
+        let field = match name.trim() {
+            "bandwidth" | "bw" => Field::Bandwidth,
+            "channels" | "ch" => Field::Channels,
+            "fps" | "frame_rate" | "framerate" => Field::FrameRate,
+            "h" | "height" => Field::Height,
+            "w" | "width" => Field::Width,
+            _ => return None,
+        };
+
+        let (op, value) = if let Some(value) = rest.strip_prefix("<=") {
+            (Op::Le, value)
+        } else if let Some(value) = rest.strip_prefix(">=") {
+            (Op::Ge, value)
+        } else if let Some(value) = rest.strip_prefix('<') {
+            (Op::Lt, value)
+        } else if let Some(value) = rest.strip_prefix('>') {
+            (Op::Gt, value)
+        } else {
+            (Op::Eq, rest.trim_start_matches('='))
+        };
+
+        Some(Self::Compare(field, op, Self::parse_value(value)?))
+    }
+
+    /// Parse numbers with an optional `k`, `m` or `g` multiplier, `5M` is 5000000.
+    /// A trailing `p` is allowed for heights, e.g. `720p`.
+    fn parse_value(value: &str) -> Option<f64> {
+        let value = value.trim().to_ascii_lowercase();
+        let value = value.strip_suffix('p').unwrap_or(&value);
+
+        let (number, multiplier) = match value.chars().last()? {
+            'k' => (&value[..value.len() - 1], 1e3),
+            'm' => (&value[..value.len() - 1], 1e6),
+            'g' => (&value[..value.len() - 1], 1e9),
+            _ => (value, 1.0),
+        };
+
+        number.parse::<f64>().ok().map(|x| x * multiplier)
+    }
+
+    fn matches(&self, stream: &MediaPlaylist) -> bool {
+        match self {
+            Self::Codec(codec) => {
+                let prefixes = Self::CODEC_ALIASES
+                    .iter()
+                    .find(|(alias, _)| alias == codec)
+                    .map_or(vec![codec.as_str()], |(_, x)| x.to_vec());
+
+                stream
+                    .codecs
+                    .iter()
+                    .flat_map(|x| x.split(','))
+                    .map(|x| x.trim().to_ascii_lowercase())
+                    .any(|x| prefixes.iter().any(|prefix| x.starts_with(prefix)))
+            }
+            Self::Compare(field, op, value) => {
+                let actual = match field {
+                    Field::Bandwidth => stream.bandwidth.map(|x| x as f64),
+                    Field::Channels => stream.channels.map(|x| x as f64),
+                    Field::FrameRate => stream.frame_rate.map(|x| x as f64),
+                    Field::Height => stream.resolution.map(|(_, h)| h as f64),
+                    Field::Width => stream.resolution.map(|(w, _)| w as f64),
+                };
+
+                actual.is_some_and(|actual| match op {
+                    Op::Eq => (actual - *value).abs() < 0.01,
+                    Op::Ge => actual >= *value,
+                    Op::Gt => actual > *value,
+                    Op::Le => actual <= *value,
+                    Op::Lt => actual < *value,
+                })
+            }
+            Self::DolbyVision => stream.dolby_vision.is_some(),
+            Self::Hdr => stream.is_hdr(),
+            Self::Role(role, negate) => stream.has_role(role) != *negate,
+            Self::Sdr => !stream.is_hdr(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> SelectOptions {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn indices() {
+        let opts = parse("1,2,3");
+        assert_eq!(opts.stream_indices, HashSet::from([0, 1, 2]));
+        assert!(opts.strict_indices);
+
+        let opts = parse("v=2:a=skip");
+        assert_eq!(opts.stream_indices, HashSet::from([1]));
+        assert!(!opts.strict_indices);
+        assert!(opts.aud.skip);
+    }
+
+    #[test]
+    fn codec_sections() {
+        let opts = parse("v=codec:hvc1,bw<=5M:a=codec:ec-3,ch>=6");
+
+        assert!(matches!(
+            opts.vid.filters.as_slice(),
+            [
+                Filter::Codec(codec),
+                Filter::Compare(Field::Bandwidth, Op::Le, bandwidth),
+            ] if codec == "hvc1" && *bandwidth == 5e6
+        ));
+        assert!(matches!(
+            opts.aud.filters.as_slice(),
+            [
+                Filter::Codec(codec),
+                Filter::Compare(Field::Channels, Op::Ge, channels),
+            ] if codec == "ec-3" && *channels == 6.0
+        ));
+        assert!(opts.sub.filters.is_empty());
+    }
+
+    #[test]
+    fn codec_last_in_section() {
+        let opts = parse("v=1080p,codec:hevc:s=en");
+
+        assert!(matches!(
+            opts.vid.filters.as_slice(),
+            [Filter::Codec(codec)] if codec == "hevc"
+        ));
+        assert!(matches!(
+            opts.vid.resolutions.as_slice(),
+            [(name, Resolution::Class(1920, 1080))] if name == "1080p"
+        ));
+        assert_eq!(opts.sub.languages, HashSet::from(["en".to_owned()]));
+    }
+
+    #[test]
+    fn language_quality() {
+        let opts = parse("a=en,de:best:s=en,forced");
+
+        assert_eq!(
+            opts.aud.languages,
+            HashSet::from(["en".to_owned(), "de".to_owned()])
+        );
+        assert!(matches!(opts.aud.quality, Quality::Best));
+        assert_eq!(opts.sub.languages, HashSet::from(["en".to_owned()]));
+        assert!(matches!(
+            opts.sub.filters.as_slice(),
+            [Filter::Role(role, false)] if role == "forced"
+        ));
+    }
+
+    #[test]
+    fn strategies_and_roles() {
+        let opts = parse("a=best-per-lang,top3,!description");
+
+        assert!(opts.aud.per_language);
+        assert_eq!(opts.aud.limit, Some(3));
+        assert!(matches!(
+            opts.aud.filters.as_slice(),
+            [Filter::Role(role, true)] if role == "description"
+        ));
+    }
+
+    #[test]
+    fn ids() {
+        let opts = parse("id=AB12CD3, ef45678");
+        assert_eq!(
+            opts.stream_ids,
+            HashSet::from(["ab12cd3".to_owned(), "ef45678".to_owned()])
+        );
+        assert!(opts.strict_indices);
+
+        let opts = parse("id=ab12cd3:s=en");
+        assert_eq!(opts.stream_ids, HashSet::from(["ab12cd3".to_owned()]));
+        assert!(!opts.strict_indices);
+    }
+
+    #[test]
+    fn filter_values() {
+        assert!(matches!(
+            Filter::parse("fps>=50"),
+            Some(Filter::Compare(Field::FrameRate, Op::Ge, x)) if x == 50.0
+        ));
+        assert!(matches!(
+            Filter::parse("height<720p"),
+            Some(Filter::Compare(Field::Height, Op::Lt, x)) if x == 720.0
+        ));
+        assert!(matches!(
+            Filter::parse("bw=1.5k"),
+            Some(Filter::Compare(Field::Bandwidth, Op::Eq, x)) if x == 1500.0
+        ));
+        assert!(Filter::parse("bw<=fast").is_none());
+        assert!(Filter::parse("en").is_none());
+    }
+}
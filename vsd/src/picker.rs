@@ -0,0 +1,373 @@
+use crate::{
+    downloader::{Stream, Streams},
+    options::MuxOptions,
+    playlist::{MediaPlaylist, MediaType},
+};
+use anyhow::{Result, bail};
+use colored::Colorize;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::Print,
+    terminal::{self, ClearType},
+};
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+/// Full screen stream picker showing stream details, with filter as you type and a preview of
+/// the ffmpeg mux layout.
+pub struct Picker<'a> {
+    cursor: usize,
+    filter: String,
+    mux: &'a MuxOptions,
+    offset: usize,
+    preview: bool,
+    rows: Vec<Row<'a>>,
+}
+
+struct Row<'a> {
+    details: String,
+    /// Lowercase text matched against the filter.
+    haystack: String,
+    index: usize,
+    selected: bool,
+    stream: &'a MediaPlaylist,
+}
+
+/// Alternate screen in raw mode, the terminal is restored when dropped.
+struct Screen;
+
+impl Screen {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stderr(), terminal::EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stderr(), cursor::Show, terminal::LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl<'a> Picker<'a> {
+    const HELP: &'static str =
+        "↑/↓ move, space toggle, ctrl+a toggle all, tab mux preview, enter confirm, esc clear/quit";
+
+    /// Streams without a duration of their own use the presentation `duration` for size estimates.
+    pub fn new(
+        streams: &'a [(usize, MediaPlaylist)],
+        selected: &HashSet<usize>,
+        mux: &'a MuxOptions,
+        duration: Option<f32>,
+    ) -> Self {
+        let mut rows = Vec::new();
+
+        for media_type in [MediaType::Video, MediaType::Audio, MediaType::Subtitles] {
+            for (i, stream) in streams.iter().filter(|(_, s)| s.media_type == media_type) {
+                let details = format!(
+                    "{} | {}",
+                    stream.id,
+                    stream.details(stream.duration().or(duration))
+                );
+
+                rows.push(Row {
+                    haystack: format!("{} {}", Self::header(&media_type), details).to_lowercase(),
+                    details,
+                    index: *i,
+                    selected: selected.contains(i),
+                    stream,
+                });
+            }
+        }
+
+        Self {
+            cursor: 0,
+            filter: String::new(),
+            mux,
+            offset: 0,
+            preview: false,
+            rows,
+        }
+    }
+
+    /// Show the picker until the selection is confirmed and return the selected stream indices.
+    pub fn run(mut self) -> Result<HashSet<usize>> {
+        let _screen = Screen::enter()?;
+
+        loop {
+            self.draw()?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+
+            if key.kind != KeyEventKind::Release && !self.handle(key)? {
+                break;
+            }
+        }
+
+        Ok(self
+            .rows
+            .iter()
+            .filter(|x| x.selected)
+            .map(|x| x.index)
+            .collect())
+    }
+
+    fn header(media_type: &MediaType) -> &'static str {
+        match media_type {
+            MediaType::Video => "─────── Video Streams ────────",
+            MediaType::Audio => "─────── Audio Streams ────────",
+            MediaType::Subtitles => "────── Subtitle Streams ──────",
+            MediaType::Undefined => "───── Undefined Streams ──────",
+        }
+    }
+
+    /// Rows containing the filter text.
+    fn visible(&self) -> Vec<usize> {
+        let filter = self.filter.to_lowercase();
+
+        self.rows
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| x.haystack.contains(&filter))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Update the picker for a key press, returns false once the selection is confirmed.
+    fn handle(&mut self, key: KeyEvent) -> Result<bool> {
+        let visible = self.visible();
+        let last = visible.len().saturating_sub(1);
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        match key.code {
+            KeyCode::Char('c') if ctrl => bail!("Stream selection was cancelled."),
+            KeyCode::Esc if self.filter.is_empty() => bail!("Stream selection was cancelled."),
+            KeyCode::Esc => {
+                self.filter.clear();
+                self.cursor = 0;
+            }
+            KeyCode::Enter => return Ok(false),
+            KeyCode::Tab => self.preview = !self.preview,
+            KeyCode::Up => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Char('p') if ctrl => self.cursor = self.cursor.saturating_sub(1),
+            KeyCode::Down => self.cursor = (self.cursor + 1).min(last),
+            KeyCode::Char('n') if ctrl => self.cursor = (self.cursor + 1).min(last),
+            KeyCode::PageUp => self.cursor = self.cursor.saturating_sub(10),
+            KeyCode::PageDown => self.cursor = (self.cursor + 10).min(last),
+            KeyCode::Home => self.cursor = 0,
+            KeyCode::End => self.cursor = last,
+            KeyCode::Char(' ') => {
+                if let Some(&i) = visible.get(self.cursor) {
+                    self.rows[i].selected = !self.rows[i].selected;
+                }
+            }
+            KeyCode::Char('a') if ctrl => {
+                let selected = !visible.iter().all(|&i| self.rows[i].selected);
+
+                for &i in &visible {
+                    self.rows[i].selected = selected;
+                }
+            }
+            KeyCode::Backspace => {
+                self.filter.pop();
+                self.cursor = 0;
+            }
+            KeyCode::Char(c) if !ctrl => {
+                self.filter.push(c);
+                self.cursor = 0;
+            }
+            _ => (),
+        }
+
+        Ok(true)
+    }
+
+    fn draw(&mut self) -> Result<()> {
+        let (width, height) = terminal::size()?;
+        let (width, height) = (width as usize, height as usize);
+        let visible = self.visible();
+        let selected = self.rows.iter().filter(|x| x.selected).count();
+
+        let mut lines = vec![
+            format!(
+                "{} {}",
+                "Select streams to download".bold(),
+                format!("({selected} selected)").dimmed()
+            ),
+            format!("{} {}", "Filter:".cyan(), self.filter),
+        ];
+
+        let mut list = Vec::new();
+        let mut cursor_line = 0;
+        let mut media_type = None;
+
+        for (position, &i) in visible.iter().enumerate() {
+            let row = &self.rows[i];
+
+            if media_type != Some(&row.stream.media_type) {
+                media_type = Some(&row.stream.media_type);
+                list.push(Self::header(&row.stream.media_type).cyan().to_string());
+            }
+
+            let text = Self::truncate(&row.details, width.saturating_sub(6));
+            let text = if row.selected {
+                text.green().to_string()
+            } else {
+                text
+            };
+
+            if position == self.cursor {
+                // Keep the media type header in view when scrolling back to a group.
+                cursor_line = if list.last().is_some_and(|x| x.contains('─')) {
+                    list.len() - 1
+                } else {
+                    list.len()
+                };
+                list.push(format!(
+                    "{} [{}] {}",
+                    ">".cyan().bold(),
+                    if row.selected { "x" } else { " " },
+                    text
+                ));
+            } else {
+                list.push(format!(
+                    "  [{}] {}",
+                    if row.selected { "x" } else { " " },
+                    text
+                ));
+            }
+        }
+
+        if visible.is_empty() {
+            list.push("No streams match the filter.".dimmed().to_string());
+        }
+
+        let preview = if self.preview {
+            self.preview_lines(width)
+        } else {
+            Vec::new()
+        };
+
+        let room = height
+            .saturating_sub(lines.len() + preview.len() + 1)
+            .max(1);
+
+        if cursor_line < self.offset {
+            self.offset = cursor_line;
+        } else if cursor_line + 1 >= self.offset + room {
+            self.offset = (cursor_line + 2).saturating_sub(room);
+        }
+
+        lines.extend(list.into_iter().skip(self.offset).take(room));
+        lines.extend(preview);
+
+        let mut stderr = io::stderr();
+        queue!(stderr, terminal::Clear(ClearType::All))?;
+
+        for (y, line) in lines.iter().enumerate().take(height.saturating_sub(1)) {
+            queue!(stderr, cursor::MoveTo(0, y as u16), Print(line))?;
+        }
+
+        queue!(
+            stderr,
+            cursor::MoveTo(0, height.saturating_sub(1) as u16),
+            Print(Self::truncate(Self::HELP, width).dimmed())
+        )?;
+        stderr.flush()?;
+        Ok(())
+    }
+
+    /// Ffmpeg command which would mux the selected streams.
+    fn preview_lines(&self, width: usize) -> Vec<String> {
+        let streams = Streams(
+            self.rows
+                .iter()
+                .filter(|x| x.selected)
+                .map(|x| Stream {
+                    dispositions: x.stream.dispositions(),
+                    gaps: false,
+                    language: x.stream.language.clone(),
+                    media_type: x.stream.media_type.clone(),
+                    path: x.stream.path(None),
+                })
+                .collect(),
+        );
+
+        let mut lines = vec!["──────── Mux Preview ─────────".cyan().to_string()];
+        let vid_count = streams
+            .0
+            .iter()
+            .filter(|x| x.media_type == MediaType::Video)
+            .count();
+
+        if streams.0.is_empty() {
+            lines.push("No streams are selected.".dimmed().to_string());
+        } else if vid_count > 1 {
+            lines.push(
+                "Multiple vid streams are saved separately, without muxing."
+                    .yellow()
+                    .to_string(),
+            );
+        } else if let Some(output) = &self.mux.output {
+            if output.as_os_str() == "-"
+                && streams.0.len() == 1
+                && streams.0[0].media_type != MediaType::Subtitles
+            {
+                lines.push("Stream is written to stdout as-is, without muxing.".to_owned());
+            } else {
+                let command = format!(
+                    "ffmpeg {}",
+                    streams
+                        .args(output, &self.mux.subs_codec, self.mux.fill_gaps)
+                        .join(" ")
+                );
+                lines.extend(Self::wrap(&command, width));
+            }
+        } else {
+            lines.push(
+                "Streams are saved separately, use --output to mux them."
+                    .dimmed()
+                    .to_string(),
+            );
+        }
+
+        lines
+    }
+
+    fn truncate(s: &str, width: usize) -> String {
+        if s.chars().count() > width {
+            let mut truncated = s.chars().take(width.saturating_sub(1)).collect::<String>();
+            truncated.push('…');
+            truncated
+        } else {
+            s.to_owned()
+        }
+    }
+
+    fn wrap(text: &str, width: usize) -> Vec<String> {
+        let mut lines = vec![String::new()];
+
+        for word in text.split(' ') {
+            let line = lines.last_mut().unwrap();
+
+            if !line.is_empty() && line.chars().count() + word.chars().count() + 1 > width {
+                lines.push(word.to_owned());
+            } else {
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.push_str(word);
+            }
+        }
+
+        lines
+    }
+}
@@ -1,50 +1,98 @@
 use crate::{
-    options::{Interaction, SelectOptions},
+    options::{Interaction, MuxOptions, SelectOptions},
     progress::ByteSize,
+    request::{Cache, Request},
     selector::StreamSelector,
 };
 use anyhow::Result;
 use colored::Colorize;
 use log::info;
-use reqwest::{
-    Client, Url,
-    header::{self, HeaderValue},
-};
+use reqwest::{Client, Url, header::HeaderValue};
 use serde::Serialize;
-use std::{cmp::Reverse, fmt::Display, path::PathBuf, sync::Arc};
+use std::{cmp::Reverse, collections::HashMap, fmt::Display, path::PathBuf, sync::Arc};
 
 #[derive(Serialize)]
 pub struct MasterPlaylist {
+    /// Presentation duration in seconds, used to estimate stream sizes before their segments are
+    /// known.
+    pub duration: Option<f32>,
     pub playlist_type: PlaylistType,
     pub uri: String,
     pub streams: Vec<MediaPlaylist>,
 }
 
-#[derive(Default, Serialize)]
+#[derive(Clone, Default, Serialize)]
 pub struct MediaPlaylist {
+    /// Rendition may be chosen without user preference (`AUTOSELECT=YES`).
+    pub autoselect: bool,
     pub bandwidth: Option<u64>,
     pub channels: Option<f32>,
     pub codecs: Option<String>,
+    /// Rendition is played by default (`DEFAULT=YES`).
+    pub default: bool,
+    /// Dolby Vision codec string, e.g. `dvh1.08.07`, either primary or supplemental.
+    pub dolby_vision: Option<String>,
     pub extension: Option<String>,
+    /// Subtitles only cover foreign or hard to understand dialogue (`FORCED=YES` or dash
+    /// `forced-subtitle` role).
+    pub forced: bool,
     pub frame_rate: Option<f32>,
+    /// Rendition group (`EXT-X-MEDIA:GROUP-ID`) this stream belongs to.
+    pub group_id: Option<String>,
+    /// Rendition groups referenced by this variant.
+    pub groups: Groups,
     pub id: String,
     pub i_frame: bool,
     pub language: Option<String>,
     pub live: bool,
+    pub low_latency: bool,
     pub media_sequence: u64,
     pub media_type: MediaType,
     pub playlist_type: PlaylistType,
     pub resolution: Option<(u64, u64)>,
+    /// Dash `Role`/`Accessibility` values and hls `CHARACTERISTICS`, normalized to dash role names
+    /// like `main`, `commentary`, `description` or `caption`.
+    pub roles: Vec<String>,
     pub segments: Vec<Segment>,
+    /// Preferred start point (`EXT-X-START:TIME-OFFSET`) in seconds, negative values are relative
+    /// to the end of the playlist.
+    pub start_offset: Option<f32>,
     pub uri: String,
+    /// Hls variables (`EXT-X-DEFINE`) of the master playlist, which can be imported by this playlist.
+    pub variables: HashMap<String, String>,
+    pub video_range: Option<VideoRange>,
+}
+
+/// Rendition groups referenced by an hls variant (`AUDIO`, `SUBTITLES` and `VIDEO` attributes).
+#[derive(Clone, Default, Serialize)]
+pub struct Groups {
+    pub audio: Option<String>,
+    pub subtitles: Option<String>,
+    pub video: Option<String>,
+}
+
+impl Groups {
+    pub fn get(&self, media_type: &MediaType) -> Option<&String> {
+        match media_type {
+            MediaType::Audio => self.audio.as_ref(),
+            MediaType::Subtitles => self.subtitles.as_ref(),
+            MediaType::Video => self.video.as_ref(),
+            MediaType::Undefined => None,
+        }
+    }
 }
 
 #[derive(Clone, Default, Serialize)]
 pub struct Segment {
     pub duration: f32,
+    /// Segment is marked with `EXT-X-GAP` and must not be downloaded.
+    pub gap: bool,
     pub key: Option<Key>,
     pub map: Option<Map>,
+    pub parts: Vec<Part>,
     pub range: Option<Range>,
+    /// Media start time in seconds, relative to the start of the presentation.
+    pub start: Option<f64>,
     pub uri: String,
 }
 
@@ -57,13 +105,22 @@ pub struct Key {
     pub uri: Option<String>,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, PartialEq, Serialize)]
 pub struct Map {
     pub range: Option<Range>,
     pub uri: String,
 }
 
-#[derive(Clone, Serialize)]
+/// Partial segment (`EXT-X-PART`) of a low-latency hls playlist.
+#[derive(Clone, PartialEq, Serialize)]
+pub struct Part {
+    pub duration: f32,
+    pub independent: bool,
+    pub range: Option<Range>,
+    pub uri: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct Range {
     pub end: u64,
     pub start: u64,
@@ -94,6 +151,14 @@ pub enum KeyMethod {
     SampleAes,
 }
 
+/// Dynamic range of a video stream (hls `VIDEO-RANGE`).
+#[derive(Clone, PartialEq, Serialize)]
+pub enum VideoRange {
+    Hlg,
+    Pq,
+    Sdr,
+}
+
 #[allow(clippy::infallible_try_from)]
 impl TryFrom<&Range> for HeaderValue {
     type Error = std::convert::Infallible;
@@ -107,12 +172,13 @@ impl Key {
     pub async fn key(
         &self,
         base_url: &Url,
+        cache: &Cache,
         client: &Client,
         query: &Vec<(String, String)>,
     ) -> Result<[u8; 16]> {
         let url = base_url.join(self.uri.as_ref().unwrap())?;
-        let bytes = client.get(url).query(query).send().await?.bytes().await?;
-        Ok(bytes.as_ref().try_into()?)
+        let bytes = cache.fetch(client, url, query, None).await?;
+        Ok(bytes.as_slice().try_into()?)
     }
 
     pub fn iv(&self, sequence: u64) -> Result<[u8; 16]> {
@@ -174,7 +240,7 @@ impl MasterPlaylist {
             info!("{}", header.cyan());
             for (i, stream) in self.streams.iter().enumerate() {
                 if stream.media_type == media_type {
-                    info!("{:>2}) {}", i + 1, stream);
+                    info!("{:>2}) {} | {}", i + 1, stream.id.dimmed(), stream);
                 }
             }
         }
@@ -186,15 +252,108 @@ impl MasterPlaylist {
         self,
         opts: &mut SelectOptions,
         interaction: Interaction,
+        mux: &MuxOptions,
     ) -> Result<Self> {
         Ok(Self {
-            streams: StreamSelector::new(self.streams, interaction).select(opts)?,
+            streams: StreamSelector::new(self.streams, interaction, mux, self.duration)
+                .select(opts)?,
             ..self
         })
     }
 }
 
 impl MediaPlaylist {
+    /// Find the Dolby Vision entry of a codecs list, `SUPPLEMENTAL-CODECS` brands (`/db4h`) are
+    /// dropped.
+    pub fn find_dolby_vision(codecs: &str) -> Option<String> {
+        codecs
+            .split(',')
+            .map(|x| x.split('/').next().unwrap_or_default().trim())
+            .find(|x| {
+                matches!(
+                    x.split('.')
+                        .next()
+                        .map(|x| x.to_ascii_lowercase())
+                        .as_deref(),
+                    Some("dva1" | "dvav" | "dvh1" | "dvhe")
+                )
+            })
+            .map(|x| x.to_owned())
+    }
+
+    /// Dolby Vision profile, e.g. `8` for `dvh1.08.07`.
+    pub fn dolby_vision_profile(&self) -> Option<u8> {
+        self.dolby_vision
+            .as_ref()
+            .and_then(|x| x.split('.').nth(1))
+            .and_then(|x| x.parse().ok())
+    }
+
+    /// Stream uses a high dynamic range transfer function or Dolby Vision.
+    pub fn is_hdr(&self) -> bool {
+        self.dolby_vision.is_some()
+            || matches!(self.video_range, Some(VideoRange::Hlg | VideoRange::Pq))
+    }
+
+    /// Total duration of the segments in seconds.
+    pub fn duration(&self) -> Option<f32> {
+        if self.segments.is_empty() {
+            return None;
+        }
+
+        Some(self.segments.iter().map(|x| x.duration).sum())
+    }
+
+    /// Untruncated stream details with the estimated size for a duration, used by the interactive
+    /// picker.
+    pub fn details(&self, duration: Option<f32>) -> String {
+        Details(self, duration).to_string()
+    }
+
+    /// Whether the stream has a role. Hls has no `main` role, so default renditions without any
+    /// characteristics are treated as main.
+    pub fn has_role(&self, role: &str) -> bool {
+        match role {
+            "default" => self.default,
+            "forced" => self.forced,
+            "main" if matches!(self.playlist_type, PlaylistType::Hls) => {
+                self.default && self.roles.is_empty()
+            }
+            _ => self.roles.iter().any(|x| x == role),
+        }
+    }
+
+    /// Ffmpeg `-disposition` flags for the stream.
+    pub fn dispositions(&self) -> Vec<&'static str> {
+        let mut dispositions = Vec::new();
+
+        if self.default {
+            dispositions.push("default");
+        }
+        if self.forced {
+            dispositions.push("forced");
+        }
+
+        for role in &self.roles {
+            let flags: &[&str] = match role.as_str() {
+                "caption" => &["captions", "hearing_impaired"],
+                "commentary" => &["comment"],
+                "description" => &["descriptions", "visual_impaired"],
+                "dub" => &["dub"],
+                "sdh" => &["hearing_impaired"],
+                _ => &[],
+            };
+
+            for flag in flags {
+                if !dispositions.contains(flag) {
+                    dispositions.push(flag);
+                }
+            }
+        }
+
+        dispositions
+    }
+
     pub fn default_kid(&self) -> Option<String> {
         self.segments
             .first()
@@ -230,6 +389,7 @@ impl MediaPlaylist {
 
     pub async fn fetch_init_seg(
         &self,
+        cache: &Cache,
         client: &Client,
         query: &Vec<(String, String)>,
     ) -> Result<Option<Arc<Vec<u8>>>> {
@@ -237,14 +397,42 @@ impl MediaPlaylist {
             return Ok(None);
         };
 
-        let url = self.uri.parse::<Url>().unwrap().join(&map.uri)?;
-        let mut request = client.get(url).query(query);
-        if let Some(range) = &map.range {
-            request = request.header(header::RANGE, range);
+        Ok(Some(self.fetch_map(map, cache, client, query).await?))
+    }
+
+    /// Fetch every distinct init segment, in the order their maps first appear.
+    pub async fn fetch_init_segs(
+        &self,
+        cache: &Cache,
+        client: &Client,
+        query: &Vec<(String, String)>,
+    ) -> Result<Vec<Arc<Vec<u8>>>> {
+        let mut maps = Vec::new();
+
+        for map in self.segments.iter().filter_map(|x| x.map.as_ref()) {
+            if !maps.contains(&map) {
+                maps.push(map);
+            }
         }
 
-        let bytes = request.send().await?.bytes().await?;
-        Ok(Some(Arc::new(bytes.to_vec())))
+        let mut init_segs = Vec::with_capacity(maps.len());
+
+        for map in maps {
+            init_segs.push(self.fetch_map(map, cache, client, query).await?);
+        }
+
+        Ok(init_segs)
+    }
+
+    pub async fn fetch_map(
+        &self,
+        map: &Map,
+        cache: &Cache,
+        client: &Client,
+        query: &Vec<(String, String)>,
+    ) -> Result<Arc<Vec<u8>>> {
+        let url = self.uri.parse::<Url>().unwrap().join(&map.uri)?;
+        cache.fetch(client, url, query, map.range.as_ref()).await
     }
 
     pub async fn fetch_split_seg(
@@ -261,16 +449,9 @@ impl MediaPlaylist {
         let segment = self.segments.remove(0);
         let url = base_url.join(&segment.uri)?;
 
-        let content_length: u64 = client
-            .head(url)
-            .query(query)
-            .send()
-            .await?
-            .headers()
-            .get(header::CONTENT_LENGTH)
-            .and_then(|v| v.to_str().ok())
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(0);
+        let content_length = Request::new(client, url, query, None)?
+            .content_length(client)
+            .await?;
 
         if content_length == 0 {
             self.segments.push(segment);
@@ -285,13 +466,101 @@ impl MediaPlaylist {
                 map: if i == 0 { segment.map.clone() } else { None },
                 key: if i == 0 { segment.key.clone() } else { None },
                 duration: segment.duration,
+                gap: false,
+                parts: Vec::new(),
                 range: Some(Range { start, end }),
+                start: if i == 0 { segment.start } else { None },
                 uri: segment.uri.clone(),
             });
         }
 
         Ok(())
     }
+
+    /// Drop the segments before the preferred start point (`EXT-X-START`). The first kept segment
+    /// inherits the key and map in effect.
+    pub fn seek_to_start(&mut self) {
+        let Some(offset) = self.start_offset else {
+            return;
+        };
+
+        let total = self.segments.iter().map(|x| x.duration as f64).sum::<f64>();
+        let offset = if offset < 0.0 {
+            (total + offset as f64).max(0.0)
+        } else {
+            (offset as f64).min(total)
+        };
+
+        let mut elapsed = 0.0;
+        let mut skip = 0;
+
+        for segment in &self.segments[..self.segments.len().saturating_sub(1)] {
+            if elapsed + segment.duration as f64 > offset {
+                break;
+            }
+
+            elapsed += segment.duration as f64;
+            skip += 1;
+        }
+
+        if skip == 0 {
+            return;
+        }
+
+        let key = self.segments[..=skip]
+            .iter()
+            .rev()
+            .find_map(|x| x.key.clone());
+        let map = self.segments[..=skip]
+            .iter()
+            .rev()
+            .find_map(|x| x.map.clone());
+
+        self.segments.drain(..skip);
+        self.segments[0].key = key;
+        self.segments[0].map = map;
+        self.media_sequence += skip as u64;
+    }
+}
+
+impl MediaType {
+    /// Classify a single RFC 6381 codec string, e.g. `avc1.64001f` or `mp4a.40.2`.
+    pub fn from_codec(codec: &str) -> Option<Self> {
+        let fourcc = codec.trim().split('.').next()?.to_ascii_lowercase();
+
+        match fourcc.as_str() {
+            "av01" | "avc1" | "avc3" | "dva1" | "dvav" | "dvh1" | "dvhe" | "encv" | "hev1"
+            | "hvc1" | "mp4v" | "vp08" | "vp09" | "vp8" | "vp9" => Some(Self::Video),
+            "ac-3" | "ac-4" | "alac" | "dtsc" | "dtse" | "dtsh" | "dtsl" | "dtsx" | "ec-3"
+            | "enca" | "flac" | "mha1" | "mhm1" | "mp3" | "mp4a" | "opus" | "vorbis" => {
+                Some(Self::Audio)
+            }
+            "c608" | "stpp" | "tx3g" | "wvtt" => Some(Self::Subtitles),
+            _ => None,
+        }
+    }
+}
+
+impl VideoRange {
+    /// Parse an hls `VIDEO-RANGE` value.
+    pub fn from_hls(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_uppercase().as_str() {
+            "HLG" => Some(Self::Hlg),
+            "PQ" => Some(Self::Pq),
+            "SDR" => Some(Self::Sdr),
+            _ => None,
+        }
+    }
+
+    /// Parse a `urn:mpeg:mpegB:cicp:TransferCharacteristics` value (ITU-T H.273).
+    pub fn from_transfer_characteristics(value: &str) -> Option<Self> {
+        match value.trim() {
+            "1" | "6" | "13" | "14" | "15" => Some(Self::Sdr),
+            "16" => Some(Self::Pq),
+            "18" => Some(Self::Hlg),
+            _ => None,
+        }
+    }
 }
 
 impl Display for MediaType {
@@ -347,6 +616,30 @@ impl MediaPlaylist {
             .unwrap_or_else(|| "?".into())
     }
 
+    fn fmt_bitrate(&self) -> String {
+        match self.bandwidth {
+            Some(b) if b >= 1_000_000 => format!("{:.1} Mbps", b as f64 / 1e6),
+            Some(b) => format!("{} kbps", b / 1000),
+            None => "? kbps".into(),
+        }
+    }
+
+    fn fmt_size(&self, duration: Option<f32>) -> String {
+        match (self.bandwidth, duration) {
+            (Some(b), Some(d)) => format!("~{}", ByteSize((b as f64 / 8.0 * d as f64) as usize)),
+            _ => "?".into(),
+        }
+    }
+
+    fn fmt_duration(duration: Option<f32>) -> String {
+        let Some(duration) = duration else {
+            return "?".into();
+        };
+
+        let secs = duration.round() as u64;
+        format!("{}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+    }
+
     fn fmt_codecs(&self) -> String {
         Self::truncate(self.codecs.as_deref().unwrap_or("?"), 10)
     }
@@ -355,6 +648,60 @@ impl MediaPlaylist {
         Self::truncate(self.language.as_deref().unwrap_or("?"), 9)
     }
 
+    /// Dolby Vision profile and hdr transfer function, sdr is left out.
+    fn fmt_range(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.dolby_vision.is_some() {
+            match self.dolby_vision_profile() {
+                Some(profile) => write!(f, " | DV p{profile}")?,
+                None => write!(f, " | DV")?,
+            }
+        }
+
+        match self.video_range {
+            Some(VideoRange::Hlg) => write!(f, " | HLG"),
+            Some(VideoRange::Pq) => write!(f, " | PQ"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Default and forced flags followed by the roles.
+    fn fmt_roles(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let roles = [("default", self.default), ("forced", self.forced)]
+            .into_iter()
+            .filter(|(_, x)| *x)
+            .map(|(x, _)| x)
+            .chain(self.roles.iter().map(|x| x.as_str()))
+            .collect::<Vec<_>>();
+
+        if !roles.is_empty() {
+            write!(f, " | {}", roles.join(","))?;
+        }
+
+        Ok(())
+    }
+
+    /// Group of a rendition or the groups referenced by a variant.
+    fn fmt_groups(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(group_id) = &self.group_id {
+            return write!(f, " | group={group_id}");
+        }
+
+        let groups = [
+            ("video", &self.groups.video),
+            ("audio", &self.groups.audio),
+            ("subs", &self.groups.subtitles),
+        ]
+        .into_iter()
+        .filter_map(|(name, group_id)| group_id.as_ref().map(|x| format!("{name}={x}")))
+        .collect::<Vec<_>>();
+
+        if !groups.is_empty() {
+            write!(f, " | {}", groups.join(","))?;
+        }
+
+        Ok(())
+    }
+
     pub fn display(&self) -> String {
         self.to_string()
             .split('|')
@@ -364,6 +711,63 @@ impl MediaPlaylist {
     }
 }
 
+struct Details<'a>(&'a MediaPlaylist, Option<f32>);
+
+impl Display for Details<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Self(stream, duration) = self;
+        let codecs = stream.codecs.as_deref().unwrap_or("?");
+        let language = stream.language.as_deref().unwrap_or("?");
+
+        match stream.media_type {
+            MediaType::Video => {
+                write!(
+                    f,
+                    "{:>9} | {:>9} | {:>9} | {:>8} | {} fps | {}",
+                    stream.fmt_resolution(),
+                    stream.fmt_bitrate(),
+                    stream.fmt_size(*duration),
+                    MediaPlaylist::fmt_duration(*duration),
+                    stream.frame_rate.map_or("?".into(), |r| r.to_string()),
+                    codecs
+                )?;
+                if stream.live {
+                    write!(f, " | live")?;
+                }
+                if stream.i_frame {
+                    write!(f, " | iframe")?;
+                }
+                stream.fmt_range(f)?;
+            }
+            MediaType::Audio => {
+                write!(
+                    f,
+                    "{:>9} | {:>9} | {:>9} | {:>8} | {} ch | {}",
+                    language,
+                    stream.fmt_bitrate(),
+                    stream.fmt_size(*duration),
+                    MediaPlaylist::fmt_duration(*duration),
+                    stream.channels.map_or("?".into(), |c| c.to_string()),
+                    codecs
+                )?;
+                if stream.live {
+                    write!(f, " | live")?;
+                }
+                stream.fmt_roles(f)?;
+            }
+            MediaType::Subtitles => {
+                write!(f, "{:>9} | {}", language, codecs)?;
+                stream.fmt_roles(f)?;
+            }
+            MediaType::Undefined => {
+                write!(f, "{}", stream.uri)?;
+            }
+        }
+
+        stream.fmt_groups(f)
+    }
+}
+
 impl Display for MediaPlaylist {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self.media_type {
@@ -382,6 +786,8 @@ impl Display for MediaPlaylist {
                 if self.i_frame {
                     write!(f, " | iframe")?;
                 }
+                self.fmt_range(f)?;
+                self.fmt_groups(f)?;
             }
             MediaType::Audio => {
                 write!(
@@ -395,6 +801,8 @@ impl Display for MediaPlaylist {
                 if self.live {
                     write!(f, " | live")?;
                 }
+                self.fmt_roles(f)?;
+                self.fmt_groups(f)?;
             }
             MediaType::Subtitles => {
                 write!(
@@ -404,6 +812,8 @@ impl Display for MediaPlaylist {
                     "?KiB",
                     self.fmt_codecs()
                 )?;
+                self.fmt_roles(f)?;
+                self.fmt_groups(f)?;
             }
             MediaType::Undefined => {
                 write!(f, "{:>9} | {:>9} | {:>10}", "?", "?", "?")?;
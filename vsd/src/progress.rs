@@ -1,10 +1,16 @@
 use colored::Colorize;
 use std::{
     io::{self, Write},
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
     time::Instant,
 };
 
+/// Bytes downloaded across all streams of the current job.
+pub static DOWNLOADED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
 struct ProgressInner {
     counter: usize,
     id: String,
@@ -31,11 +37,18 @@ impl Progress {
         }
     }
 
+    /// Grow the total as segments of a live stream appear.
+    pub fn set_total(&self, total: usize) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.total = inner.total.max(total);
+    }
+
     pub fn update(&self, chunk_bytes: usize) {
         let mut inner = self.inner.lock().unwrap();
 
         inner.counter += 1;
         inner.total_bytes += chunk_bytes;
+        DOWNLOADED_BYTES.fetch_add(chunk_bytes, Ordering::SeqCst);
 
         let remaining_bytes =
             ((inner.total_bytes as f64 / inner.counter as f64) * inner.total as f64) as usize;
@@ -0,0 +1,251 @@
+use crate::{playlist::Range, utils};
+use anyhow::{Result, anyhow, bail};
+use base64::Engine;
+use log::debug;
+use reqwest::{Client, RequestBuilder, Url, header};
+use std::{
+    collections::HashMap,
+    io::SeekFrom,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    fs::{self, File},
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+
+/// A request for a playlist resource, served over HTTP, from the local disk
+/// or from memory for `data:` URIs.
+pub enum Request {
+    Data(Vec<u8>),
+    File { path: PathBuf, range: Option<Range> },
+    Http(Box<RequestBuilder>),
+}
+
+impl Request {
+    pub fn new(
+        client: &Client,
+        url: Url,
+        query: &Vec<(String, String)>,
+        range: Option<&Range>,
+    ) -> Result<Self> {
+        if url.scheme() == "data" {
+            let mut data = decode_data_uri(url.as_str())?;
+
+            if let Some(range) = range {
+                let end = (range.end as usize + 1).min(data.len());
+                data = data.get(range.start as usize..end).unwrap_or(&[]).to_vec();
+            }
+
+            return Ok(Self::Data(data));
+        }
+
+        if url.scheme() == "file" {
+            return Ok(Self::File {
+                path: url
+                    .to_file_path()
+                    .map_err(|_| anyhow!("{} is not a valid local file path.", url))?,
+                range: range.cloned(),
+            });
+        }
+
+        let mut request = client.get(url).query(query);
+
+        if let Some(range) = range {
+            request = request.header(header::RANGE, range);
+        }
+
+        Ok(Self::Http(Box::new(request)))
+    }
+
+    pub async fn send(&self) -> Result<Vec<u8>> {
+        match self {
+            Self::Data(data) => Ok(data.clone()),
+            Self::File { path, range } => {
+                let mut file = File::open(path)
+                    .await
+                    .map_err(|e| anyhow!("{} ({})", path.to_string_lossy(), e))?;
+                let mut data = Vec::new();
+
+                if let Some(range) = range {
+                    file.seek(SeekFrom::Start(range.start)).await?;
+                    file.take(range.end - range.start + 1)
+                        .read_to_end(&mut data)
+                        .await?;
+                } else {
+                    file.read_to_end(&mut data).await?;
+                }
+
+                Ok(data)
+            }
+            Self::Http(request) => {
+                let response = request
+                    .try_clone()
+                    .ok_or_else(|| anyhow!("Streaming request bodies cannot be sent again."))?
+                    .send()
+                    .await?;
+                utils::fetch_bytes(response).await
+            }
+        }
+    }
+
+    pub async fn content_length(&self, client: &Client) -> Result<u64> {
+        match self {
+            Self::Data(data) => Ok(data.len() as u64),
+            Self::File { path, .. } => Ok(fs::metadata(path).await?.len()),
+            Self::Http(request) => {
+                let request = request
+                    .try_clone()
+                    .ok_or_else(|| anyhow!("Streaming request bodies cannot be sent again."))?
+                    .build()?;
+                Ok(client
+                    .head(request.url().clone())
+                    .headers(request.headers().clone())
+                    .send()
+                    .await?
+                    .headers()
+                    .get(header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|s| s.parse().ok())
+                    .unwrap_or(0))
+            }
+        }
+    }
+}
+
+type CacheKey = (String, Option<(u64, u64)>);
+
+/// Per-download cache for small immutable resources (init maps, keys and playlists),
+/// keyed by url plus byte range.
+#[derive(Clone, Default)]
+pub struct Cache(Arc<Mutex<HashMap<CacheKey, Arc<Vec<u8>>>>>);
+
+impl Cache {
+    pub async fn fetch(
+        &self,
+        client: &Client,
+        url: Url,
+        query: &Vec<(String, String)>,
+        range: Option<&Range>,
+    ) -> Result<Arc<Vec<u8>>> {
+        let key = (url.to_string(), range.map(|x| (x.start, x.end)));
+
+        if let Some(data) = self.0.lock().unwrap().get(&key) {
+            debug!("Cached response reused for {}", key.0);
+            return Ok(data.clone());
+        }
+
+        let data = Arc::new(Request::new(client, url, query, range)?.send().await?);
+        self.0.lock().unwrap().insert(key, data.clone());
+        Ok(data)
+    }
+}
+
+/// Decode a `data:[<mediatype>][;base64],<data>` URI (RFC 2397).
+pub fn decode_data_uri(uri: &str) -> Result<Vec<u8>> {
+    let Some((header, data)) = uri.strip_prefix("data:").and_then(|x| x.split_once(',')) else {
+        bail!("{} is not a valid data uri.", uri);
+    };
+
+    let data = percent_encoding::percent_decode_str(data).collect::<Vec<u8>>();
+
+    if header
+        .rsplit(';')
+        .next()
+        .is_some_and(|x| x.eq_ignore_ascii_case("base64"))
+    {
+        // Url safe and unpadded encodings are common in inlined playlists.
+        let data = data
+            .into_iter()
+            .filter(|x| !x.is_ascii_whitespace() && *x != b'=')
+            .map(|x| match x {
+                b'-' => b'+',
+                b'_' => b'/',
+                x => x,
+            })
+            .collect::<Vec<u8>>();
+        Ok(base64::engine::general_purpose::STANDARD_NO_PAD.decode(data)?)
+    } else {
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_uri_base64() {
+        assert_eq!(
+            decode_data_uri("data:text/plain;base64,aGVsbG8gd29ybGQ=").unwrap(),
+            b"hello world"
+        );
+        assert_eq!(
+            decode_data_uri("data:;base64,aGVsbG8gd29ybGQ").unwrap(),
+            b"hello world"
+        );
+    }
+
+    #[test]
+    fn data_uri_url_safe_base64() {
+        // 0xfb 0xff 0xbf encodes to "+/+/" with the standard alphabet.
+        assert_eq!(
+            decode_data_uri("data:application/octet-stream;base64,-_-_").unwrap(),
+            [0xfb, 0xff, 0xbf]
+        );
+        assert_eq!(
+            decode_data_uri("data:application/octet-stream;BASE64,-_-_%0A").unwrap(),
+            [0xfb, 0xff, 0xbf]
+        );
+    }
+
+    #[test]
+    fn data_uri_percent_encoded() {
+        assert_eq!(
+            decode_data_uri("data:application/vnd.apple.mpegurl,%23EXTM3U%0Aa%20b.ts").unwrap(),
+            b"#EXTM3U\na b.ts"
+        );
+        assert_eq!(decode_data_uri("data:,").unwrap(), b"");
+    }
+
+    #[test]
+    fn data_uri_invalid() {
+        assert!(decode_data_uri("data:text/plain;base64").is_err());
+        assert!(decode_data_uri("https://example.com/a,b").is_err());
+        assert!(decode_data_uri("data:;base64,a").is_err());
+    }
+
+    #[tokio::test]
+    async fn cache_data_uri_range() {
+        let cache = Cache::default();
+        let client = Client::new();
+        let url = "data:;base64,MDEyMzQ1Njc4OQ==".parse::<Url>().unwrap();
+        let range = |start, end| Range { end, start };
+
+        let data = cache
+            .fetch(&client, url.clone(), &Vec::new(), Some(&range(2, 5)))
+            .await
+            .unwrap();
+        assert_eq!(data.as_slice(), b"2345");
+
+        // Ranges are cached separately and clamped to the data.
+        let data = cache
+            .fetch(&client, url.clone(), &Vec::new(), Some(&range(8, 20)))
+            .await
+            .unwrap();
+        assert_eq!(data.as_slice(), b"89");
+
+        let data = cache
+            .fetch(&client, url.clone(), &Vec::new(), None)
+            .await
+            .unwrap();
+        assert_eq!(data.as_slice(), b"0123456789");
+        assert_eq!(cache.0.lock().unwrap().len(), 3);
+
+        let data = cache
+            .fetch(&client, url, &Vec::new(), Some(&range(20, 30)))
+            .await
+            .unwrap();
+        assert!(data.is_empty());
+    }
+}
@@ -1,32 +1,54 @@
 use crate::{
-    options::{Interaction, Quality, SelectOptions},
+    language::Language,
+    options::{Interaction, MuxOptions, Preferences, Quality, Resolution, SelectOptions},
+    picker::Picker,
     playlist::{MediaPlaylist, MediaType},
 };
 use anyhow::Result;
 use colored::Colorize;
-use log::info;
-use requestty::{Question, question::Choice};
+use log::{info, warn};
+use requestty::question::Choice;
 use std::{
+    cmp::Reverse,
     collections::HashSet,
-    io::{self, Write},
+    io::{self, IsTerminal, Write},
 };
 
-pub struct StreamSelector {
+pub struct StreamSelector<'a> {
+    /// Presentation duration in seconds, used to estimate stream sizes.
+    duration: Option<f32>,
     interaction: Interaction,
+    mux: &'a MuxOptions,
     selected_indices: HashSet<usize>,
     streams: Vec<(usize, MediaPlaylist)>,
 }
 
-impl StreamSelector {
-    pub fn new(streams: Vec<MediaPlaylist>, interaction: Interaction) -> Self {
+impl<'a> StreamSelector<'a> {
+    pub fn new(
+        streams: Vec<MediaPlaylist>,
+        interaction: Interaction,
+        mux: &'a MuxOptions,
+        duration: Option<f32>,
+    ) -> Self {
         Self {
+            duration,
             interaction,
+            mux,
             selected_indices: HashSet::new(),
             streams: streams.into_iter().enumerate().collect(),
         }
     }
 
     pub fn select(mut self, opts: &mut SelectOptions) -> Result<Vec<MediaPlaylist>> {
+        for id in &opts.stream_ids {
+            match self.streams.iter().find(|(_, s)| &s.id == id) {
+                Some((i, _)) => {
+                    opts.stream_indices.insert(*i);
+                }
+                None => warn!("No stream found with id {id}."),
+            }
+        }
+
         if opts.strict_indices {
             self.selected_indices = opts.stream_indices.clone();
         } else {
@@ -46,9 +68,10 @@ impl StreamSelector {
         let vid_data = self
             .streams
             .iter()
-            .filter(|(_, s)| s.media_type == MediaType::Video)
+            .filter(|(_, s)| s.media_type == MediaType::Video && opts.vid.matches(s))
             .map(|(i, s)| (*i, s.resolution))
             .collect::<Vec<_>>();
+        Self::warn_unmatched(&opts.vid, vid_data.is_empty(), "video");
 
         if opts.vid.all {
             for (i, _) in &vid_data {
@@ -79,11 +102,28 @@ impl StreamSelector {
             }
         }
 
-        for (i, resolution) in &vid_data {
-            if let Some((w, h)) = resolution
-                && opts.vid.resolutions.contains(&(*w as u16, *h as u16)) {
-                    indices.insert(*i);
+        for (query, resolution) in &opts.vid.resolutions {
+            let matched = vid_data
+                .iter()
+                .filter_map(|(i, x)| x.map(|x| (*i, x)))
+                .filter(|(_, x)| resolution.matches(*x));
+
+            let index = match resolution {
+                Resolution::Nearest(height) => matched
+                    .min_by_key(|(_, (w, h))| {
+                        let class = Resolution::class_height(*w, *h);
+                        (class.abs_diff(*height), class > *height)
+                    })
+                    .map(|(i, _)| i),
+                _ => matched.map(|(i, _)| i).next(),
+            };
+
+            if let Some(i) = index.or_else(|| Self::closest_lower(&vid_data, resolution.target())) {
+                if index.is_none() {
+                    warn!("No video stream matches {query}, selecting the closest lower quality.");
                 }
+                indices.insert(i);
+            }
         }
 
         if opts.vid.skip && !indices.is_empty() {
@@ -94,20 +134,55 @@ impl StreamSelector {
             }
         } else if !opts.vid.skip {
             if indices.is_empty()
-                && let Some((i, _)) = vid_data.first() {
-                    indices.insert(*i);
-                }
+                && let Some((i, _)) = vid_data.first()
+            {
+                indices.insert(*i);
+            }
             self.selected_indices.extend(indices);
         }
     }
 
-    fn select_aud_streams(&mut self, opts: &mut SelectOptions) {
-        let aud_data = self
+    /// Best stream at or below a height, or the lowest one when all of them are higher. Streams
+    /// are sorted from the highest resolution.
+    fn closest_lower(vid_data: &[(usize, Option<(u64, u64)>)], height: u64) -> Option<usize> {
+        let mut resolutions = vid_data
+            .iter()
+            .filter_map(|(i, x)| x.map(|(w, h)| (*i, Resolution::class_height(w, h))));
+
+        resolutions
+            .clone()
+            .find(|(_, x)| *x <= height)
+            .or_else(|| resolutions.next_back())
+            .map(|(i, _)| i)
+    }
+
+    /// Streams of a type, with renditions from the groups of the selected variants first.
+    fn group_sorted(&self, media_type: MediaType) -> Vec<&(usize, MediaPlaylist)> {
+        let groups = self
+            .streams
+            .iter()
+            .filter(|(i, _)| self.selected_indices.contains(i))
+            .filter_map(|(_, s)| s.groups.get(&media_type))
+            .collect::<HashSet<_>>();
+
+        let mut streams = self
             .streams
             .iter()
-            .filter(|(_, s)| s.media_type == MediaType::Audio)
+            .filter(|(_, s)| s.media_type == media_type)
+            .collect::<Vec<_>>();
+        streams.sort_by_key(|(_, s)| !s.group_id.as_ref().is_some_and(|x| groups.contains(x)));
+        streams
+    }
+
+    fn select_aud_streams(&mut self, opts: &mut SelectOptions) {
+        let mut aud_data = self
+            .group_sorted(MediaType::Audio)
+            .into_iter()
+            .filter(|(_, s)| opts.aud.matches(s))
             .map(|(i, s)| (*i, s.language.clone()))
             .collect::<Vec<_>>();
+        Self::sort_by_quality(&opts.aud, &mut aud_data);
+        Self::warn_unmatched(&opts.aud, aud_data.is_empty(), "audio");
 
         if opts.aud.all {
             for (i, _) in &aud_data {
@@ -124,19 +199,18 @@ impl StreamSelector {
             }
         }
 
-        for (i, lang) in &aud_data {
-            if let Some(lang) = lang
-                && opts.aud.contains_exact_lang(lang) {
+        // Exact languages first, then other regions and finally other scripts.
+        for distance in 0..=2 {
+            for (i, lang) in &aud_data {
+                if let Some(lang) = lang
+                    && opts.aud.take_language(lang, distance)
+                {
                     indices.insert(*i);
                 }
+            }
         }
 
-        for (i, lang) in &aud_data {
-            if let Some(lang) = lang
-                && opts.aud.contains_siml_lang(lang) {
-                    indices.insert(*i);
-                }
-        }
+        Self::apply_strategies(&opts.aud, &aud_data, &mut indices);
 
         if opts.aud.skip && !indices.is_empty() {
             for (i, _) in &aud_data {
@@ -146,20 +220,23 @@ impl StreamSelector {
             }
         } else if !opts.aud.skip {
             if indices.is_empty()
-                && let Some((i, _)) = aud_data.first() {
-                    indices.insert(*i);
-                }
+                && let Some((i, _)) = aud_data.first()
+            {
+                indices.insert(*i);
+            }
             self.selected_indices.extend(indices);
         }
     }
 
     fn select_sub_streams(&mut self, opts: &mut SelectOptions) {
-        let sub_data = self
-            .streams
-            .iter()
-            .filter(|(_, s)| s.media_type == MediaType::Subtitles)
+        let mut sub_data = self
+            .group_sorted(MediaType::Subtitles)
+            .into_iter()
+            .filter(|(_, s)| opts.sub.matches(s))
             .map(|(i, s)| (*i, s.language.clone()))
             .collect::<Vec<_>>();
+        Self::sort_by_quality(&opts.sub, &mut sub_data);
+        Self::warn_unmatched(&opts.sub, sub_data.is_empty(), "subtitle");
 
         if opts.sub.all {
             for (i, _) in &sub_data {
@@ -176,19 +253,18 @@ impl StreamSelector {
             }
         }
 
-        for (i, lang) in &sub_data {
-            if let Some(lang) = lang
-                && opts.sub.contains_exact_lang(lang) {
+        // Exact languages first, then other regions and finally other scripts.
+        for distance in 0..=2 {
+            for (i, lang) in &sub_data {
+                if let Some(lang) = lang
+                    && opts.sub.take_language(lang, distance)
+                {
                     indices.insert(*i);
                 }
+            }
         }
 
-        for (i, lang) in &sub_data {
-            if let Some(lang) = lang
-                && opts.sub.contains_siml_lang(lang) {
-                    indices.insert(*i);
-                }
-        }
+        Self::apply_strategies(&opts.sub, &sub_data, &mut indices);
 
         if opts.sub.skip && !indices.is_empty() {
             for (i, _) in &sub_data {
@@ -198,10 +274,71 @@ impl StreamSelector {
             }
         } else if !opts.sub.skip {
             if indices.is_empty()
-                && let Some((i, _)) = sub_data.first() {
+                && let Some((i, _)) = sub_data.first()
+            {
+                indices.insert(*i);
+            }
+            self.selected_indices.extend(indices);
+        }
+    }
+
+    /// Streams are already sorted by quality (`MasterPlaylist::sort_streams`) and `group_sorted`
+    /// puts renditions from the groups of the selected variants first. That order is only kept
+    /// without a quality strategy, `best`, `worst` and `best-per-lang` compare every stream.
+    fn sort_by_quality(prefs: &Preferences, data: &mut [(usize, Option<String>)]) {
+        match prefs.quality {
+            Quality::Best => data.sort_by_key(|(i, _)| *i),
+            Quality::None => (),
+            Quality::Worst => data.sort_by_key(|(i, _)| Reverse(*i)),
+        }
+    }
+
+    /// Apply `best-per-lang` and `topN` on top of the already selected streams.
+    fn apply_strategies(
+        prefs: &Preferences,
+        data: &[(usize, Option<String>)],
+        indices: &mut HashSet<usize>,
+    ) {
+        if prefs.per_language {
+            let mut languages = indices
+                .iter()
+                .filter_map(|i| data.iter().find(|(x, _)| x == i))
+                .map(|(_, lang)| Self::language_code(lang))
+                .collect::<HashSet<_>>();
+
+            for (i, lang) in data {
+                if languages.insert(Self::language_code(lang)) {
                     indices.insert(*i);
                 }
-            self.selected_indices.extend(indices);
+            }
+        }
+
+        if let Some(limit) = prefs.limit {
+            let selected = data
+                .iter()
+                .map(|(i, _)| *i)
+                .filter(|i| indices.is_empty() || indices.contains(i))
+                .take(limit)
+                .collect();
+            *indices = selected;
+        }
+    }
+
+    /// Normalized language tag, regional variants like `pt-BR` and `pt-PT` are kept apart.
+    fn language_code(lang: &Option<String>) -> Option<String> {
+        lang.as_ref().map(|x| match Language::parse(x) {
+            Some(x) => [Some(x.code), x.script, x.region]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join("-"),
+            None => x.to_owned(),
+        })
+    }
+
+    fn warn_unmatched(prefs: &Preferences, empty: bool, name: &str) {
+        if empty && !prefs.filters.is_empty() {
+            warn!("No {name} streams match the attribute filters, none will be selected.");
         }
     }
 
@@ -228,59 +365,28 @@ impl StreamSelector {
     }
 
     fn interact_modern(self) -> Result<Vec<MediaPlaylist>> {
-        let vid_len = self
-            .streams
-            .iter()
-            .filter(|(_, s)| s.media_type == MediaType::Video)
-            .count();
-        let aud_len = self
-            .streams
-            .iter()
-            .filter(|(_, s)| s.media_type == MediaType::Audio)
-            .count();
-
-        let question = Question::multi_select("streams")
-            .message("Select streams to download")
-            .should_loop(false)
-            .choices_with_default(self.build_choices())
-            .transform(|choices, _, backend| {
-                let summary = choices
-                    .iter()
-                    .map(|x| {
-                        x.text
-                            .split('|')
-                            .map(|s| s.trim())
-                            .collect::<Vec<_>>()
-                            .join(" ")
-                    })
-                    .collect::<Vec<_>>()
-                    .join(" | ");
-                backend.write_styled(&requestty::prompt::style::Stylize::cyan(&summary))
-            })
-            .build();
+        if !io::stderr().is_terminal() {
+            return self.interact_raw();
+        }
 
-        let answer = requestty::prompt_one(question)?;
-        let selected_indices = answer
-            .as_list_items()
-            .unwrap()
-            .iter()
-            .map(|item| {
-                let idx = item.index;
-                if idx <= vid_len {
-                    idx - 1
-                } else if idx <= vid_len + 1 + aud_len {
-                    idx - 2
-                } else {
-                    idx - 3
-                }
-            })
-            .collect::<HashSet<_>>();
+        let selected_indices = Picker::new(
+            &self.streams,
+            &self.selected_indices,
+            self.mux,
+            self.duration,
+        )
+        .run()?;
 
         Ok(self
             .streams
             .into_iter()
             .filter_map(|(i, stream)| {
                 if selected_indices.contains(&i) {
+                    info!(
+                        "Stream [{}] {}",
+                        stream.media_type.to_string().yellow(),
+                        stream.to_string().cyan()
+                    );
                     Some(stream)
                 } else {
                     None
@@ -340,8 +446,9 @@ impl StreamSelector {
         }
 
         info!("{}", "------------------------------".cyan());
-        print!("Press enter to proceed with defaults.\nOr select streams (1, 2, etc.): ");
-        io::stdout().flush()?;
+        // Stdout may be the downloaded stream (`--output -`), so prompts go to stderr.
+        eprint!("Press enter to proceed with defaults.\nOr select streams (1, 2, etc.): ");
+        io::stderr().flush()?;
 
         let mut input = String::new();
         io::stdin().read_line(&mut input)?;
@@ -381,3 +488,128 @@ impl StreamSelector {
         Ok(streams)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playlist::Groups;
+
+    /// A variant referencing the low bitrate audio group, with a better audio rendition outside
+    /// of it. Streams are in `sort_streams` order.
+    fn streams() -> Vec<MediaPlaylist> {
+        let audio = |id: &str, bandwidth, language: &str| MediaPlaylist {
+            bandwidth: Some(bandwidth),
+            group_id: Some(id.to_owned()),
+            id: id.to_owned(),
+            language: Some(language.to_owned()),
+            media_type: MediaType::Audio,
+            ..Default::default()
+        };
+
+        vec![
+            MediaPlaylist {
+                groups: Groups {
+                    audio: Some("en-lo".to_owned()),
+                    ..Default::default()
+                },
+                id: "vid".to_owned(),
+                media_type: MediaType::Video,
+                ..Default::default()
+            },
+            audio("en-hi", 256000, "en"),
+            audio("de-hi", 256000, "de"),
+            audio("en-lo", 64000, "en"),
+        ]
+    }
+
+    /// Video streams in `sort_streams` order, with two renditions of the 720p class.
+    fn videos() -> Vec<MediaPlaylist> {
+        let video = |id: &str, w, h| MediaPlaylist {
+            id: id.to_owned(),
+            media_type: MediaType::Video,
+            resolution: Some((w, h)),
+            ..Default::default()
+        };
+
+        vec![
+            video("1080", 1920, 1080),
+            video("720-hi", 1280, 720),
+            video("720-lo", 1280, 720),
+            video("480", 854, 480),
+        ]
+    }
+
+    fn select_from(streams: Vec<MediaPlaylist>, media_type: MediaType, query: &str) -> Vec<String> {
+        let mux = MuxOptions::default();
+        StreamSelector::new(streams, Interaction::None, &mux, None)
+            .select(&mut query.parse().unwrap())
+            .unwrap()
+            .into_iter()
+            .filter(|x| x.media_type == media_type)
+            .map(|x| x.id)
+            .collect()
+    }
+
+    fn select(query: &str) -> Vec<String> {
+        select_from(streams(), MediaType::Audio, query)
+    }
+
+    fn select_video(query: &str) -> Vec<String> {
+        select_from(videos(), MediaType::Video, query)
+    }
+
+    #[test]
+    fn grouped_language() {
+        assert_eq!(select("a=en"), ["en-lo"]);
+    }
+
+    #[test]
+    fn grouped_top() {
+        assert_eq!(select("a=top1"), ["en-lo"]);
+    }
+
+    #[test]
+    fn worst_language() {
+        assert_eq!(select("a=de,en:worst"), ["de-hi", "en-lo"]);
+    }
+
+    #[test]
+    fn best_per_language() {
+        assert_eq!(select("a=best-per-lang"), ["en-hi", "de-hi"]);
+    }
+
+    #[test]
+    fn best_language() {
+        assert_eq!(select("a=en:best"), ["en-hi"]);
+        assert_eq!(select("a=en,de:best"), ["en-hi", "de-hi"]);
+    }
+
+    #[test]
+    fn exact_resolution() {
+        assert_eq!(select_video("v=720p"), ["720-hi"]);
+        assert_eq!(select_video("v=1280x720"), ["720-hi"]);
+        assert_eq!(select_video("v=<=720p"), ["720-hi"]);
+        assert_eq!(select_video("v=~720p"), ["720-hi"]);
+    }
+
+    #[test]
+    fn nearest_resolution() {
+        assert_eq!(select_video("v=~1000p"), ["1080"]);
+        assert_eq!(select_video("v=~650p"), ["720-hi"]);
+        // 900p is as far from 1080p as from 720p, the lower one wins.
+        assert_eq!(select_video("v=~900p"), ["720-hi"]);
+    }
+
+    #[test]
+    fn closest_lower_resolution() {
+        assert_eq!(select_video("v=1440p"), ["1080"]);
+        assert_eq!(select_video("v=<=900p"), ["720-hi"]);
+        assert_eq!(select_video("v=1024x576"), ["480"]);
+    }
+
+    #[test]
+    fn no_lower_resolution() {
+        assert_eq!(select_video("v=360p"), ["480"]);
+        assert_eq!(select_video("v=<=240p"), ["480"]);
+    }
+}
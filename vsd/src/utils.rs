@@ -19,9 +19,7 @@ pub async fn fetch_bytes(response: Response) -> Result<Vec<u8>> {
 
 pub fn find_ffmpeg() -> Option<PathBuf> {
     let mut paths = Vec::new();
-    if let Some(path) = env::current_dir().ok() {
-        paths.push(path);
-    }
+    paths.extend(env::current_dir());
     if let Some(path) = env::current_exe()
         .ok()
         .and_then(|path| path.parent().map(|p| p.to_path_buf()))
@@ -38,6 +36,9 @@ pub fn find_ffmpeg() -> Option<PathBuf> {
     paths.into_iter().map(|x| x.join(bin)).find(|x| x.exists())
 }
 
+/// Short stream id, stable across runs. Query strings and fragments are ignored as they
+/// usually carry expiring tokens.
 pub fn gen_id(base_url: &str, uri: &str) -> String {
-    blake3::hash(format!("{}+{}", base_url, uri).as_bytes()).to_hex()[..7].to_owned()
+    let strip = |x: &str| x.split(['?', '#']).next().unwrap_or_default().to_owned();
+    blake3::hash(format!("{}+{}", strip(base_url), strip(uri)).as_bytes()).to_hex()[..7].to_owned()
 }